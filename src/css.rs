@@ -0,0 +1,324 @@
+// just enough css for emphasis: simple selectors and a few properties
+use crossterm::style::Color;
+use roxmltree::Node;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Default)]
+pub struct Style {
+    pub italic: Option<bool>,
+    pub bold: Option<bool>,
+    pub underline: Option<bool>,
+    pub strike: Option<bool>,
+    pub small_caps: Option<bool>,
+    pub align: Option<Align>,
+    // display: block, or inline
+    pub block: Option<bool>,
+    // Some(None) for the reader's own color
+    pub color: Option<Option<Color>>,
+}
+
+impl Style {
+    // later declarations win
+    fn merge(&mut self, other: &Style) {
+        self.italic = other.italic.or(self.italic);
+        self.bold = other.bold.or(self.bold);
+        self.underline = other.underline.or(self.underline);
+        self.strike = other.strike.or(self.strike);
+        self.small_caps = other.small_caps.or(self.small_caps);
+        self.align = other.align.or(self.align);
+        self.block = other.block.or(self.block);
+        self.color = other.color.or(self.color);
+    }
+}
+
+// a compound selector, eg p.note.small or #title
+#[derive(Clone)]
+struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Selector {
+    // None for the ones we don't handle, eg descendants or pseudo classes
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.is_empty() || s.contains(|c: char| c.is_whitespace() || ">+~:[*".contains(c)) {
+            return None;
+        }
+        let mut sel = Selector {
+            tag: None,
+            id: None,
+            classes: Vec::new(),
+        };
+        let start = s.find(['.', '#']).unwrap_or(s.len());
+        if start > 0 {
+            sel.tag = Some(s[..start].to_ascii_lowercase());
+        }
+        let mut rest = &s[start..];
+        while let Some(kind) = rest.chars().next() {
+            let end = rest[1..].find(['.', '#']).map_or(rest.len(), |i| i + 1);
+            let name = rest[1..end].to_string();
+            if name.is_empty() {
+                return None;
+            }
+            match kind {
+                '#' => sel.id = Some(name),
+                _ => sel.classes.push(name),
+            }
+            rest = &rest[end..];
+        }
+        Some(sel)
+    }
+    fn specificity(&self) -> usize {
+        self.id.as_ref().map_or(0, |_| 100)
+            + self.classes.len() * 10
+            + self.tag.as_ref().map_or(0, |_| 1)
+    }
+    fn matches(&self, n: Node) -> bool {
+        let classes = n.attribute("class").unwrap_or_default();
+        self.tag
+            .as_ref()
+            .is_none_or(|tag| n.tag_name().name().eq_ignore_ascii_case(tag))
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| n.attribute("id") == Some(id))
+            && self
+                .classes
+                .iter()
+                .all(|class| classes.split_whitespace().any(|c| c == class))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Sheet {
+    rules: Vec<(Selector, Style)>,
+}
+
+impl Sheet {
+    pub fn parse(text: &str) -> Self {
+        let text = strip_comments(text);
+        let mut s = text.as_str();
+        let mut rules = Vec::new();
+        loop {
+            s = s.trim_start();
+            if s.starts_with('@') {
+                // skip at-rules, with or without a block
+                let semi = s.find(';').unwrap_or(s.len());
+                let open = s.find('{').unwrap_or(s.len());
+                s = if semi < open {
+                    &s[(semi + 1).min(s.len())..]
+                } else {
+                    &s[block_end(s, open)..]
+                };
+                continue;
+            }
+            let (Some(open), Some(close)) = (s.find('{'), s.find('}')) else {
+                break;
+            };
+            if close < open {
+                s = &s[close + 1..];
+                continue;
+            }
+            let style = declarations(&s[open + 1..close]);
+            for sel in s[..open].split(',').filter_map(Selector::parse) {
+                rules.push((sel, style.clone()));
+            }
+            s = &s[close + 1..];
+        }
+        Sheet { rules }
+    }
+    pub fn extend(&mut self, other: &Sheet) {
+        self.rules.extend(other.rules.iter().cloned());
+    }
+    // the style of an element, from the sheet and its style attribute
+    pub fn style(&self, n: Node) -> Style {
+        let mut rules: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|(sel, _)| sel.matches(n))
+            .collect();
+        rules.sort_by_key(|(sel, _)| sel.specificity());
+        let mut style = Style::default();
+        for (_, s) in rules {
+            style.merge(s);
+        }
+        // old html, eg <p align="center">
+        if let Some(align) = n.attribute("align") {
+            style.merge(&declarations(&format!("text-align: {}", align)));
+        }
+        if let Some(inline) = n.attribute("style") {
+            style.merge(&declarations(inline));
+        }
+        style
+    }
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("/*") {
+        s.push_str(&rest[..i]);
+        rest = rest[i + 2..]
+            .find("*/")
+            .map_or("", |j| &rest[i + 2 + j + 2..]);
+    }
+    s.push_str(rest);
+    s
+}
+
+// the byte after the block opening at open, counting nested braces
+fn block_end(s: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + i + 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    s.len()
+}
+
+fn declarations(s: &str) -> Style {
+    let mut style = Style::default();
+    for decl in s.split(';') {
+        let Some((prop, value)) = decl.split_once(':') else {
+            continue;
+        };
+        let prop = prop.trim().to_ascii_lowercase();
+        let value = value.to_ascii_lowercase().replace("!important", "");
+        let value = value.trim();
+        match prop.as_str() {
+            "font-style" => match value {
+                "italic" | "oblique" => style.italic = Some(true),
+                "normal" => style.italic = Some(false),
+                _ => (),
+            },
+            "font-weight" => match value {
+                "bold" | "bolder" => style.bold = Some(true),
+                "normal" | "lighter" => style.bold = Some(false),
+                _ => {
+                    if let Ok(weight) = value.parse::<u32>() {
+                        style.bold = Some(weight >= 600);
+                    }
+                }
+            },
+            "color" => {
+                if let Some(color) = color(value) {
+                    style.color = Some(color);
+                }
+            }
+            "text-align" => match value {
+                "center" => style.align = Some(Align::Center),
+                "right" | "end" => style.align = Some(Align::Right),
+                "left" | "start" | "justify" => style.align = Some(Align::Left),
+                _ => (),
+            },
+            "text-decoration" | "text-decoration-line" => {
+                if value.contains("underline") {
+                    style.underline = Some(true);
+                }
+                if value.contains("line-through") {
+                    style.strike = Some(true);
+                }
+                if value == "none" {
+                    style.underline = Some(false);
+                    style.strike = Some(false);
+                }
+            }
+            "font-variant" | "font-variant-caps" => match value {
+                "small-caps" | "all-small-caps" => style.small_caps = Some(true),
+                "normal" => style.small_caps = Some(false),
+                _ => (),
+            },
+            "display" => match value {
+                "block" | "flex" | "grid" | "table" | "list-item" => style.block = Some(true),
+                "inline" | "inline-block" | "inline-flex" => style.block = Some(false),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+    style
+}
+
+// the nearest of the 256 terminal colors. greys are left to the reader's
+// color, eg books that set black text on a dark terminal
+fn color(value: &str) -> Option<Option<Color>> {
+    let (r, g, b) = if let Some(hex) = value.strip_prefix('#') {
+        let hex: String = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 => hex.to_string(),
+            _ => return None,
+        };
+        let v = u32::from_str_radix(&hex, 16).ok()?;
+        ((v >> 16) as u8, (v >> 8) as u8, v as u8)
+    } else if let Some(args) = value
+        .strip_prefix("rgb(")
+        .or_else(|| value.strip_prefix("rgba("))
+    {
+        let v = args
+            .trim_end_matches(')')
+            .split([',', ' ', '/'])
+            .filter(|s| !s.is_empty())
+            .take(3)
+            .map(|s| match s.strip_suffix('%') {
+                Some(p) => p.parse::<f32>().map(|p| (p * 2.55) as u8),
+                None => s.parse::<f32>().map(|v| v as u8),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        if v.len() != 3 {
+            return None;
+        }
+        (v[0], v[1], v[2])
+    } else {
+        match value {
+            "black" | "white" | "gray" | "grey" | "silver" => return Some(None),
+            "red" => (255, 0, 0),
+            "maroon" => (128, 0, 0),
+            "brown" => (165, 42, 42),
+            "orange" => (255, 165, 0),
+            "yellow" => (255, 255, 0),
+            "olive" => (128, 128, 0),
+            "lime" => (0, 255, 0),
+            "green" => (0, 128, 0),
+            "teal" => (0, 128, 128),
+            "aqua" | "cyan" => (0, 255, 255),
+            "blue" => (0, 0, 255),
+            "navy" => (0, 0, 128),
+            "purple" => (128, 0, 128),
+            "fuchsia" | "magenta" => (255, 0, 255),
+            "pink" => (255, 192, 203),
+            _ => return None,
+        }
+    };
+    if r.max(g).max(b) - r.min(g).min(b) < 48 {
+        return Some(None);
+    }
+    // the 6x6x6 cube
+    let level = |v: u8| {
+        [0i32, 95, 135, 175, 215, 255]
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &l)| (l - v as i32).abs())
+            .unwrap()
+            .0 as u8
+    };
+    Some(Some(Color::AnsiValue(
+        16 + 36 * level(r) + 6 * level(g) + level(b),
+    )))
+}
@@ -1,10 +1,111 @@
-use crossterm::style::{Attribute, Attributes};
+use crossterm::style::{Attribute, Attributes, Color};
+use hyphenation::Standard;
+use image::{ImageFormat, ImageOutputFormat};
 use roxmltree::{Document, Node, ParsingOptions};
 use std::{
-    collections::HashMap,
+    cell::{Cell, Ref, RefCell},
+    cmp::{max, min, Reverse},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fs::File,
-    io::{self, Read},
+    hash::{Hash, Hasher},
+    io::{self, Cursor, Read},
+    iter, mem,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    css::{Align, Sheet, Style},
+    graphics::Sixel,
+    i18n,
+};
+
+// bump when the same book renders to different chapter text, which moves
+// the saved byte offsets
+pub const LAYOUT: u32 = 8;
+
+// attributes the book can set, and what turns each off. normal intensity
+// ends both bold and dim
+pub const ATTRS: [(Attribute, Attribute); 5] = [
+    (Attribute::Italic, Attribute::NoItalic),
+    (Attribute::Bold, Attribute::NormalIntensity),
+    (Attribute::Dim, Attribute::NormalIntensity),
+    (Attribute::Underlined, Attribute::NoUnderline),
+    (Attribute::CrossedOut, Attribute::NotCrossedOut),
+];
+
+// how italics, bold or underlines show, for terminals that lack one
+#[derive(Clone, PartialEq)]
+pub enum Emphasis {
+    // as this attribute instead
+    As(Attribute),
+    Off,
+    // plain text between these, eg _italics_
+    Marker(String),
+}
+
+impl Emphasis {
+    // an attribute by name, none, or else a marker
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "italic" => Emphasis::As(Attribute::Italic),
+            "bold" => Emphasis::As(Attribute::Bold),
+            "dim" => Emphasis::As(Attribute::Dim),
+            "underline" => Emphasis::As(Attribute::Underlined),
+            "none" => Emphasis::Off,
+            s => Emphasis::Marker(s.to_string()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub images: bool,
+    pub tabstop: usize,
+    // first line indents instead of blank lines between paragraphs
+    pub indent: bool,
+    // blank lines between blocks
+    pub spacing: usize,
+    // bytes the parsed book may take, roughly
+    pub budget: u64,
+    // rejoin words split at source line breaks, from ocr
+    pub dehyphenate: bool,
+    // readings after ruby text, eg 漢字(かんじ)
+    pub ruby: bool,
+    // text colors from css
+    pub colors: bool,
+    // line prefix for blockquotes
+    pub quote: String,
+    // shown centered for <hr> and asterisms
+    pub scene_break: String,
+    pub italic_quotes: bool,
+    pub italic: Emphasis,
+    pub bold: Emphasis,
+    pub underline: Emphasis,
+    // columns of text when the book was opened
+    pub width: usize,
+    // for warnings
+    pub text: &'static i18n::Text,
+}
+
+pub struct Image {
+    pub id: usize,
+    // as in the book until first shown, then png
+    data: RefCell<Vec<u8>>,
+    png: Cell<bool>,
+    pub width: u32,
+    pub height: u32,
+    // bytes of the [IMG] placeholder
+    pub pos: usize,
+    pub end: usize,
+    // layout, set by Chapter::wrap
+    pub line: usize,
+    pub cols: usize,
+    pub rows: usize,
+    // lines covered, including any placeholder lines past the image
+    pub span: usize,
+    pub sent: Cell<bool>,
+    pub sixel: RefCell<Option<Sixel>>,
+}
 
 pub struct Chapter {
     pub title: String,
@@ -13,35 +114,284 @@ pub struct Chapter {
     pub lines: Vec<(usize, usize)>,
     // crossterm gives us a bitset but doesn't let us diff it, so store the state transition
     pub attrs: Vec<(usize, Attribute, Attributes)>,
+    // text color changes, None for the reader's color
+    pub colors: Vec<(usize, Option<Color>)>,
     pub links: Vec<(usize, usize, String)>,
+    // links to footnotes, as (start, url)
+    pub notes: Vec<(usize, String)>,
+    // page breaks of the print edition, as (start, page number)
+    pub pages: Vec<(usize, String)>,
+    // blocks repeated across chapters, eg anthology front matter
+    pub repeated: Vec<(usize, usize)>,
+    // preformatted blocks, scrolled instead of wrapped
+    pub nowrap: Vec<(usize, usize)>,
+    // (start, end, columns) of tables, kept as laid out and scrolled when
+    // wider than the screen
+    tables: Vec<(usize, usize, usize)>,
+    // columns of the last wrap
+    width: usize,
+    pub images: Vec<Image>,
     frag: Vec<(String, usize)>,
+    // elements with ids, to pull out footnotes
+    targets: Vec<(usize, usize)>,
+    blocks: Vec<(usize, usize)>,
+    sources: Vec<(usize, usize, String)>,
     state: Attributes,
+    color: Option<Color>,
+    // font-variant: small-caps
+    caps: bool,
+    // line breaks owed since the last block ended
+    pending: usize,
+    // emphasis markers owed before the next text, eg _ for --italic _
+    opening: String,
+    // hyphenated words used mid line, kept when rejoining
+    compounds: HashSet<String>,
+    // inside a preformatted block, keep whitespace
+    pre: bool,
+    // next number of each open list, None if unordered
+    lists: Vec<Option<i64>>,
+    // (start, end, line prefix) of indented blocks, disjoint after parsing
+    indent: Vec<(usize, usize, String)>,
+    prefix: String,
+    // (start, end, alignment) of blocks, disjoint after parsing
+    align: Vec<(usize, usize, Align)>,
+    css: Sheet,
+    opts: Options,
+}
+
+impl Chapter {
+    pub fn wrap(
+        &mut self,
+        width: usize,
+        max_rows: usize,
+        cell: (usize, usize),
+        hyphenator: Option<&Standard>,
+    ) {
+        self.lines.clear();
+        self.width = width;
+        // wrap each run of text with the same indent separately
+        let mut cuts: Vec<usize> = self
+            .nowrap
+            .iter()
+            .flat_map(|&(a, b)| [a, b])
+            .chain(self.tables.iter().flat_map(|&(a, b, _)| [a, b]))
+            .chain(self.indent.iter().flat_map(|&(a, b, _)| [a, b]))
+            .chain(iter::once(self.text.len()))
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        let mut pos = 0;
+        for end in cuts {
+            if self.is_nowrap(pos) || self.table(pos).is_some() {
+                let mut line = pos;
+                for s in self.text[pos..end].split_terminator('\n') {
+                    self.lines.push((line, line + s.len()));
+                    line += s.len() + 1;
+                }
+            } else {
+                let width = width - self.indent(pos, width).width();
+                let lines = crate::wrap(&self.text[pos..end], width, hyphenator);
+                self.lines
+                    .extend(lines.into_iter().map(|(a, b)| (pos + a, pos + b)));
+            }
+            pos = end;
+        }
+
+        // make room for each image below its placeholder
+        let (cw, ch) = cell;
+        for img in &mut self.images {
+            let (w, h) = (img.width as usize, img.height as usize);
+            let mut cols = min(width, w.div_ceil(cw)).max(1);
+            let mut rows = (cols * cw * h).div_ceil(w * ch);
+            if rows > max_rows {
+                rows = max_rows;
+                cols = (rows * ch * w / (h * cw)).max(1);
+            }
+            img.line = match self.lines.binary_search_by_key(&img.pos, |&(a, _)| a) {
+                Ok(n) => n,
+                Err(n) => n - 1,
+            };
+            img.cols = cols;
+            img.rows = rows.max(1);
+            // alt text can wrap
+            let placeholder = self.lines[img.line..]
+                .iter()
+                .take_while(|&&(a, _)| a < img.end)
+                .count();
+            img.span = max(img.rows, placeholder);
+            let at = img.line + placeholder;
+            let end = self.lines[at - 1].1;
+            self.lines.splice(
+                at..at,
+                iter::repeat_n((end, end), img.rows.saturating_sub(placeholder)),
+            );
+        }
+    }
+    // prefix of the line starting at byte, at most half the width
+    pub fn indent(&self, byte: usize, width: usize) -> &str {
+        let i = match self.indent.binary_search_by_key(&byte, |&(a, _, _)| a) {
+            Ok(i) => i,
+            Err(0) => return "",
+            Err(i) => i - 1,
+        };
+        let (_, end, ref prefix) = self.indent[i];
+        if byte >= end {
+            return "";
+        }
+        let mut cols = 0;
+        let len = prefix
+            .char_indices()
+            .find(|&(_, c)| {
+                cols += c.width().unwrap_or(0);
+                cols > width / 2
+            })
+            .map_or(prefix.len(), |(i, _)| i);
+        &prefix[..len]
+    }
+    pub fn align(&self, byte: usize) -> Align {
+        let i = match self.align.binary_search_by_key(&byte, |&(a, _, _)| a) {
+            Ok(i) => i,
+            Err(0) => return Align::Left,
+            Err(i) => i - 1,
+        };
+        match self.align[i] {
+            (_, end, align) if byte < end => align,
+            _ => Align::Left,
+        }
+    }
+    // the note with its id at byte, on one line
+    pub fn note(&self, byte: usize) -> String {
+        let target = self
+            .targets
+            .iter()
+            .filter(|t| t.0 == byte)
+            .max_by_key(|t| t.1)
+            .map(|&(a, b)| &self.text[a..b]);
+        let text = match target {
+            Some(text) if text.trim().chars().count() > 3 => text,
+            // the id is on the number, eg <p><a id="n1">1</a> Text</p>
+            _ => self
+                .blocks
+                .iter()
+                .filter(|b| b.0 <= byte && byte < b.1)
+                .min_by_key(|b| b.1 - b.0)
+                .map_or("", |&(a, b)| &self.text[a..b]),
+        };
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+    // the print page at byte, if the book marks them
+    pub fn page(&self, byte: usize) -> Option<&str> {
+        let n = self.pages.partition_point(|p| p.0 <= byte);
+        n.checked_sub(1).map(|n| self.pages[n].1.as_str())
+    }
+    pub fn is_nowrap(&self, byte: usize) -> bool {
+        let pre = match self.nowrap.binary_search_by_key(&byte, |&(a, _)| a) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(n) => byte < self.nowrap[n - 1].1,
+        };
+        pre || self.table(byte).is_some_and(|cols| cols > self.width)
+    }
+    // the columns of the table at byte
+    fn table(&self, byte: usize) -> Option<usize> {
+        self.tables
+            .iter()
+            .find(|&&(a, b, _)| a <= byte && byte < b)
+            .map(|t| t.2)
+    }
+}
+
+impl Image {
+    // png for the terminal, converted the first time. empty if it doesn't
+    // decode
+    pub fn png(&self) -> Ref<'_, Vec<u8>> {
+        if !self.png.replace(true) {
+            let mut data = self.data.borrow_mut();
+            let png = image::load_from_memory(&data).ok().and_then(|img| {
+                let mut png = Vec::new();
+                let mut out = Cursor::new(&mut png);
+                img.write_to(&mut out, ImageOutputFormat::Png).ok()?;
+                Some(png)
+            });
+            *data = png.unwrap_or_default();
+        }
+        self.data.borrow()
+    }
 }
 
 pub struct Epub {
     container: zip::ZipArchive<File>,
     rootdir: String,
+    opts: Options,
     pub chapters: Vec<Chapter>,
     pub links: HashMap<String, (usize, usize)>,
+    // the nav in order: (depth, url, title), subsections deeper
+    pub sections: Vec<(usize, String, String)>,
     pub meta: String,
+    // dc:identifier, dc:title and dc:creator, to spot copies of a book
+    pub ids: [String; 3],
+    pub language: String,
 }
 
 impl Epub {
-    pub fn new(path: &str, meta: bool) -> io::Result<Self> {
+    pub fn new(path: &str, meta: bool, opts: Options) -> io::Result<Self> {
         let file = File::open(path)?;
         let mut epub = Epub {
             container: zip::ZipArchive::new(file)?,
             rootdir: String::new(),
+            opts,
             chapters: Vec::new(),
             links: HashMap::new(),
+            sections: Vec::new(),
             meta: String::new(),
+            ids: Default::default(),
+            language: String::new(),
         };
         let chapters = epub.get_spine();
         if !meta {
+            epub.check_budget()?;
             epub.get_chapters(chapters);
         }
         Ok(epub)
     }
+    // guess the memory needed from the uncompressed sizes, before a huge book
+    // runs us out. images go first, they're usually most of it
+    fn check_budget(&mut self) -> io::Result<()> {
+        let (mut text, mut images) = (0, 0);
+        for i in 0..self.container.len() {
+            let file = self.container.by_index(i)?;
+            let name = file.name().to_ascii_lowercase();
+            if name.ends_with("html") || name.ends_with(".htm") || name.ends_with(".xml") {
+                // the string, the dom and the chapter text
+                text += file.size() * 3;
+            } else if ImageFormat::from_path(&name).is_ok() {
+                // as read, and converted to png once shown
+                images += file.size() * 2;
+            }
+        }
+        let mb = |n: u64| n.div_ceil(1 << 20);
+        if text > self.opts.budget {
+            // read anyway, with what can be left out left out
+            self.opts.images = false;
+            self.opts.colors = false;
+            self.meta.push_str(&format!(
+                "{}: {} ({} MB > {} MB)\n",
+                self.opts.text.warning,
+                self.opts.text.over_budget,
+                mb(text),
+                mb(self.opts.budget)
+            ));
+        } else if self.opts.images && text + images > self.opts.budget {
+            self.opts.images = false;
+            self.meta.push_str(&format!(
+                "{}: {} ({} MB)\n",
+                self.opts.text.warning,
+                self.opts.text.images_skipped,
+                mb(images)
+            ));
+        }
+        Ok(())
+    }
     fn get_text(&mut self, name: &str) -> String {
         let mut text = String::new();
         self.container
@@ -51,28 +401,149 @@ impl Epub {
             .unwrap();
         text
     }
+    // an image, its format and its size, read from the header. decoding waits
+    // until it's shown. None if there's nothing to show
+    fn get_image(&mut self, name: &str) -> Option<(Vec<u8>, ImageFormat, u32, u32)> {
+        let mut data = Vec::new();
+        self.container
+            .by_name(name)
+            .ok()?
+            .read_to_end(&mut data)
+            .ok()?;
+        let format = image::guess_format(&data).ok()?;
+        let reader = image::io::Reader::with_format(Cursor::new(&data), format);
+        let (w, h) = reader.into_dimensions().ok()?;
+        if w == 0 || h == 0 {
+            return None;
+        }
+        Some((data, format, w, h))
+    }
+    // the css a chapter links to or embeds, in order
+    fn stylesheet(&mut self, root: Node, path: &str, cache: &mut HashMap<String, Sheet>) -> Sheet {
+        let mut sheet = Sheet::default();
+        let Some(head) = root.children().find(|n| n.tag_name().name() == "head") else {
+            return sheet;
+        };
+        for n in head.descendants() {
+            match n.tag_name().name() {
+                "link" => {
+                    let rel = n.attribute("rel").unwrap_or_default();
+                    let href = n.attribute("href").unwrap_or_default();
+                    if !rel
+                        .split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+                    {
+                        continue;
+                    }
+                    let name = resolve(&format!("{}{}", self.rootdir, path), href);
+                    if !cache.contains_key(&name) {
+                        let mut css = String::new();
+                        if let Ok(mut file) = self.container.by_name(&name) {
+                            // a broken sheet is no sheet
+                            file.read_to_string(&mut css).ok();
+                        }
+                        cache.insert(name.clone(), Sheet::parse(&css));
+                    }
+                    sheet.extend(&cache[&name]);
+                }
+                "style" => sheet.extend(&Sheet::parse(&text(n))),
+                _ => (),
+            }
+        }
+        sheet
+    }
     fn get_chapters(&mut self, spine: Vec<(String, String)>) {
+        let mut image_id = 0;
+        let mut sheets = HashMap::new();
         for (title, path) in spine {
             // https://github.com/RazrFalcon/roxmltree/issues/12
             // UnknownEntityReference for HTML entities
             let xml = self.get_text(&format!("{}{}", self.rootdir, path));
             let opt = ParsingOptions { allow_dtd: true };
             let doc = Document::parse_with_options(&xml, opt).unwrap();
-            let body = doc.root_element().last_element_child().unwrap();
+            // an svg page has no body
+            let root = doc.root_element();
+            let body = match root.tag_name().name() {
+                "svg" => root,
+                _ => root.last_element_child().unwrap_or(root),
+            };
+            let css = self.stylesheet(root, &path, &mut sheets);
             let state = Attributes::default();
             let mut c = Chapter {
-                title,
+                title: sanitize(&title),
                 text: String::new(),
                 lines: Vec::new(),
                 attrs: vec![(0, Attribute::Reset, state)],
                 state,
+                colors: Vec::new(),
+                color: None,
+                caps: false,
                 links: Vec::new(),
+                repeated: Vec::new(),
+                nowrap: Vec::new(),
+                tables: Vec::new(),
+                width: self.opts.width,
+                images: Vec::new(),
                 frag: Vec::new(),
+                targets: Vec::new(),
+                notes: Vec::new(),
+                pages: Vec::new(),
+                blocks: Vec::new(),
+                sources: Vec::new(),
+                pending: 0,
+                opening: String::new(),
+                compounds: HashSet::new(),
+                pre: false,
+                lists: Vec::new(),
+                indent: Vec::new(),
+                prefix: String::new(),
+                align: Vec::new(),
+                css,
+                opts: self.opts.clone(),
             };
+            if self.opts.dehyphenate {
+                c.compounds = compounds(body);
+            }
             render(body, &mut c);
+            if !c.text.ends_with('\n') {
+                c.text.push('\n');
+            }
+            c.collapse();
+            // page breaks between blocks belong to the text after them
+            for (pos, _) in &mut c.pages {
+                let rest = &c.text[*pos..];
+                *pos += rest.len() - rest.trim_start().len();
+            }
+            c.indent = flatten(&c.indent);
+            c.align = flatten(&c.align);
             if c.text.trim().is_empty() {
                 continue;
             }
+            if self.opts.images {
+                let base = format!("{}{}", self.rootdir, path);
+                for (pos, end, src) in c.sources.drain(..) {
+                    let image = self.get_image(&resolve(&base, &src));
+                    if let Some((data, format, width, height)) = image {
+                        // kitty ids start at 1
+                        image_id += 1;
+                        c.images.push(Image {
+                            id: image_id,
+                            data: RefCell::new(data),
+                            png: Cell::new(format == ImageFormat::Png),
+                            width,
+                            height,
+                            pos,
+                            end,
+                            line: 0,
+                            cols: 0,
+                            rows: 0,
+                            span: 0,
+                            sent: Cell::new(false),
+                            sixel: RefCell::new(None),
+                        });
+                    }
+                }
+            }
             let relative = path.rsplit('/').next().unwrap();
             self.links
                 .insert(relative.to_string(), (self.chapters.len(), 0));
@@ -80,13 +551,55 @@ impl Epub {
                 let url = format!("{}#{}", relative, id);
                 self.links.insert(url, (self.chapters.len(), pos));
             }
-            for link in c.links.iter_mut() {
-                if link.2.starts_with('#') {
-                    link.2.insert_str(0, relative);
+            let urls = c.links.iter_mut().map(|l| &mut l.2);
+            for url in urls.chain(c.notes.iter_mut().map(|n| &mut n.1)) {
+                if url.starts_with('#') {
+                    url.insert_str(0, relative);
                 }
             }
             self.chapters.push(c);
         }
+        self.find_repeated();
+    }
+    fn find_repeated(&mut self) {
+        // short blocks like "* * *" are repeated by design
+        const MIN_LEN: usize = 40;
+        const MIN_CHAPTERS: usize = 3;
+
+        let fingerprint = |text: &str| {
+            let mut hasher = DefaultHasher::new();
+            text.trim().hash(&mut hasher);
+            hasher.finish()
+        };
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for c in &self.chapters {
+            let set: HashSet<u64> = c
+                .blocks
+                .iter()
+                .filter(|&&(start, end)| end - start >= MIN_LEN)
+                .map(|&(start, end)| fingerprint(&c.text[start..end]))
+                .collect();
+            for hash in set {
+                *counts.entry(hash).or_insert(0) += 1;
+            }
+        }
+        for c in &mut self.chapters {
+            let mut blocks: Vec<(usize, usize)> = c
+                .blocks
+                .drain(..)
+                .filter(|&(start, end)| {
+                    end - start >= MIN_LEN
+                        && counts[&fingerprint(&c.text[start..end])] >= MIN_CHAPTERS
+                })
+                .collect();
+            blocks.sort_unstable();
+            for (start, end) in blocks {
+                match c.repeated.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => c.repeated.push((start, end)),
+                }
+            }
+        }
     }
     fn get_spine(&mut self) -> Vec<(String, String)> {
         let xml = self.get_text("META-INF/container.xml");
@@ -115,10 +628,21 @@ impl Epub {
 
         meta_node.children().filter(Node::is_element).for_each(|n| {
             let name = n.tag_name().name();
-            let text = n.text();
-            if text.is_some() && name != "meta" {
-                self.meta
-                    .push_str(&format!("{}: {}\n", name, text.unwrap()));
+            match n.text() {
+                Some(text) if name != "meta" => {
+                    self.meta
+                        .push_str(&format!("{}: {}\n", name, sanitize(text)));
+                    let i = ["identifier", "title", "creator"]
+                        .iter()
+                        .position(|&id| id == name);
+                    if let Some(i) = i.filter(|&i| self.ids[i].is_empty()) {
+                        self.ids[i] = text.trim().to_string();
+                    }
+                    if name == "language" && self.language.is_empty() {
+                        self.language = text.trim().to_string();
+                    }
+                }
+                _ => (),
             }
         });
         manifest_node
@@ -136,13 +660,13 @@ impl Epub {
                 .unwrap();
             let xml = self.get_text(&format!("{}{}", self.rootdir, path));
             let doc = Document::parse(&xml).unwrap();
-            epub3(doc, &mut nav);
+            epub3(doc, &mut nav, &mut self.sections);
         } else {
             let id = spine_node.attribute("toc").unwrap_or("ncx");
             let path = manifest.get(id).unwrap();
             let xml = self.get_text(&format!("{}{}", self.rootdir, path));
             let doc = Document::parse(&xml).unwrap();
-            epub2(doc, &mut nav);
+            epub2(doc, &mut nav, &mut self.sections);
         }
         spine_node
             .children()
@@ -159,29 +683,350 @@ impl Epub {
 }
 
 impl Chapter {
-    fn render(&mut self, n: Node, open: Attribute, close: Attribute) {
-        self.state.set(open);
-        self.attrs.push((self.text.len(), open, self.state));
+    fn render(&mut self, n: Node, attr: Attribute) {
+        // css can turn it off, eg em { font-style: normal }
+        let style = self.css.style(n);
+        let off = match attr {
+            Attribute::Italic => style.italic,
+            Attribute::Bold => style.bold,
+            Attribute::Underlined => style.underline,
+            Attribute::CrossedOut => style.strike,
+            _ => None,
+        };
+        if off == Some(false) {
+            return self.render_text(n);
+        }
+        let from = self.state;
+        self.state.set(attr);
+        self.transition(from);
         self.render_text(n);
-        self.state.unset(open);
-        self.attrs.push((self.text.len(), close, self.state));
+        let from = self.state;
+        self.state.unset(attr);
+        self.transition(from);
+    }
+    // emphasis from css, undone by restore
+    fn style(&mut self, style: &Style) {
+        let from = self.state;
+        for (attr, on) in [
+            (Attribute::Italic, style.italic),
+            (Attribute::Bold, style.bold),
+            (Attribute::Underlined, style.underline),
+            (Attribute::CrossedOut, style.strike),
+        ] {
+            match on {
+                Some(true) => self.state.set(attr),
+                Some(false) => self.state.unset(attr),
+                None => (),
+            }
+        }
+        self.transition(from);
+    }
+    fn set_color(&mut self, color: Option<Color>) {
+        if color != self.color {
+            self.color = color;
+            self.colors.push((self.text.len(), color));
+        }
+    }
+    fn restore(&mut self, state: Attributes) {
+        let from = self.state;
+        self.state = state;
+        self.transition(from);
+    }
+    // the attributes state is drawn with, after --italic and the like
+    fn shown(&self, state: Attributes) -> Attributes {
+        let opts = &self.opts;
+        let mut shown = state;
+        let emphasis = [
+            (Attribute::Italic, &opts.italic),
+            (Attribute::Bold, &opts.bold),
+            (Attribute::Underlined, &opts.underline),
+        ];
+        for (attr, _) in emphasis {
+            shown.unset(attr);
+        }
+        for (attr, how) in emphasis {
+            if let (true, Emphasis::As(to)) = (state.has(attr), how) {
+                shown.set(*to);
+            }
+        }
+        shown
+    }
+    // push the attributes that changed since from
+    fn transition(&mut self, from: Attributes) {
+        self.markers(from);
+        let (from, state) = (self.shown(from), self.shown(self.state));
+        for (on, off) in ATTRS {
+            if from.has(on) == state.has(on) {
+                continue;
+            }
+            if state.has(on) {
+                self.attrs.push((self.text.len(), on, state));
+                continue;
+            }
+            self.attrs.push((self.text.len(), off, state));
+            for other in [Attribute::Bold, Attribute::Dim] {
+                if off == Attribute::NormalIntensity && state.has(other) {
+                    self.attrs.push((self.text.len(), other, state));
+                }
+            }
+        }
+    }
+    // markers for emphasis that starts or ends here, outside the spaces. an
+    // opening one waits for the text, in case a block starts first
+    fn markers(&mut self, from: Attributes) {
+        for (attr, how) in [
+            (Attribute::Italic, self.opts.italic.clone()),
+            (Attribute::Bold, self.opts.bold.clone()),
+            (Attribute::Underlined, self.opts.underline.clone()),
+        ] {
+            let Emphasis::Marker(marker) = how else {
+                continue;
+            };
+            match (from.has(attr), self.state.has(attr)) {
+                (false, true) => self.opening.push_str(&marker),
+                // nothing between, eg <em></em>
+                (true, false) if self.opening.ends_with(&marker) => {
+                    let len = self.opening.len() - marker.len();
+                    self.opening.truncate(len);
+                }
+                (true, false) => {
+                    let end = self.text.trim_end_matches([' ', '\n']).len();
+                    self.text.insert_str(end, &marker);
+                    // eg the blocks of a <br> inside, now after the marker
+                    let len = marker.len();
+                    self.remap(|pos| if pos > end { pos + len } else { pos });
+                }
+                _ => (),
+            }
+        }
+    }
+    // move every saved position after editing the text
+    fn remap(&mut self, f: impl Fn(usize) -> usize) {
+        self.attrs.iter_mut().for_each(|a| a.0 = f(a.0));
+        self.colors.iter_mut().for_each(|a| a.0 = f(a.0));
+        self.frag.iter_mut().for_each(|a| a.1 = f(a.1));
+        self.notes.iter_mut().for_each(|a| a.0 = f(a.0));
+        self.pages.iter_mut().for_each(|a| a.0 = f(a.0));
+        let ranges = self.blocks.iter_mut().chain(&mut self.nowrap);
+        for (a, b) in ranges.chain(&mut self.targets) {
+            (*a, *b) = (f(*a), f(*b));
+        }
+        for (a, b, _) in &mut self.links {
+            (*a, *b) = (f(*a), f(*b));
+        }
+        for (a, b, _) in &mut self.tables {
+            (*a, *b) = (f(*a), f(*b));
+        }
+        for (a, b, _) in &mut self.sources {
+            (*a, *b) = (f(*a), f(*b));
+        }
+        for (a, b, _) in &mut self.indent {
+            (*a, *b) = (f(*a), f(*b));
+        }
+        for (a, b, _) in &mut self.align {
+            (*a, *b) = (f(*a), f(*b));
+        }
+    }
+    // no blank lines at the start, and no more in a row than between blocks,
+    // eg from <br><br><br> or empty paragraphs. preformatted text is kept
+    fn collapse(&mut self) {
+        let max = max(self.opts.spacing, 1);
+        // byte ranges to drop
+        let mut cut: Vec<(usize, usize)> = Vec::new();
+        let mut blank = max;
+        let mut pos = 0;
+        for line in self.text.split_inclusive('\n') {
+            let len = line.len();
+            if !line.trim().is_empty() || self.is_nowrap(pos) {
+                blank = 0;
+            } else if blank >= max {
+                cut.push((pos, pos + len));
+            } else {
+                blank += 1;
+                // whitespace, eg &nbsp;
+                if len > 1 {
+                    cut.push((pos, pos + len - 1));
+                }
+            }
+            pos += len;
+        }
+        if cut.is_empty() {
+            return;
+        }
+        // the start of each cut in the new text, for binary search
+        let mut removed = 0;
+        let shifts: Vec<(usize, usize, usize)> = cut
+            .iter()
+            .map(|&(a, b)| {
+                removed += b - a;
+                (a, b, removed)
+            })
+            .collect();
+        let mut text = String::with_capacity(self.text.len() - removed);
+        let mut pos = 0;
+        for &(a, b) in &cut {
+            text.push_str(&self.text[pos..a]);
+            pos = b;
+        }
+        text.push_str(&self.text[pos..]);
+        self.text = text;
+        self.remap(|pos| {
+            let i = shifts.partition_point(|&(a, _, _)| a < pos);
+            match i.checked_sub(1).map(|i| shifts[i]) {
+                // inside a cut, to its start
+                Some((a, b, removed)) if pos < b => a - (removed - (b - a)),
+                Some((_, _, removed)) => pos - removed,
+                None => pos,
+            }
+        });
+    }
+    // lowercase as dim capitals
+    fn small_caps(&mut self, s: &str) {
+        let dim = self.state.has(Attribute::Dim);
+        for ch in s.chars() {
+            let from = self.state;
+            if ch.is_lowercase() {
+                self.state.set(Attribute::Dim);
+            } else if !ch.is_whitespace() {
+                self.state.unset(Attribute::Dim);
+            }
+            self.transition(from);
+            self.text.extend(ch.to_uppercase());
+        }
+        let from = self.state;
+        if dim {
+            self.state.set(Attribute::Dim);
+        } else {
+            self.state.unset(Attribute::Dim);
+        }
+        self.transition(from);
     }
     fn render_text(&mut self, n: Node) {
         for child in n.children() {
             render(child, self);
         }
     }
+    // start a block on a new line after some blank lines, returning its start.
+    // nested blocks share their breaks instead of adding up
+    fn open(&mut self, blank: usize) -> usize {
+        self.close(blank);
+        self.flush();
+        self.text.len()
+    }
+    fn close(&mut self, blank: usize) {
+        self.pending = max(self.pending, blank + 1);
+    }
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let have = self.text.len() - self.text.trim_end_matches('\n').len();
+            self.text
+                .extend(iter::repeat_n('\n', self.pending.saturating_sub(have)));
+        }
+        self.pending = 0;
+    }
+    // end the current line, unless it's empty
+    fn newline(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+    // a block with a prefix on each line, after any of the blocks around it
+    fn indented(&mut self, n: Node, prefix: &str, italic: bool, blank: usize) {
+        let start = self.open(blank);
+        let len = self.prefix.len();
+        self.prefix.push_str(prefix);
+        if italic {
+            self.render(n, Attribute::Italic);
+        } else {
+            self.render_text(n);
+        }
+        self.newline();
+        self.indent
+            .push((start, self.text.len(), self.prefix.clone()));
+        self.prefix.truncate(len);
+        self.blocks.push((start, self.text.len()));
+        self.close(blank);
+    }
+}
+
+// nested ranges, eg indents, to disjoint ranges, the innermost winning
+fn flatten<T: Clone + PartialEq>(ranges: &[(usize, usize, T)]) -> Vec<(usize, usize, T)> {
+    fn push<T: Clone + PartialEq>(
+        out: &mut Vec<(usize, usize, T)>,
+        start: usize,
+        end: usize,
+        prefix: &T,
+    ) {
+        match out.last_mut() {
+            _ if start >= end => (),
+            Some(last) if last.1 == start && last.2 == *prefix => last.1 = end,
+            _ => out.push((start, end, prefix.clone())),
+        }
+    }
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|&(a, b, _)| (a, Reverse(b)));
+    let mut out = Vec::new();
+    // open ranges as (end, prefix)
+    let mut stack: Vec<(usize, T)> = Vec::new();
+    let mut pos = 0;
+    for (start, end, prefix) in sorted {
+        while let Some((e, p)) = stack.last() {
+            if *e > start {
+                break;
+            }
+            push(&mut out, pos, *e, p);
+            pos = max(pos, *e);
+            stack.pop();
+        }
+        if let Some((_, p)) = stack.last() {
+            push(&mut out, pos, start, p);
+        }
+        pos = start;
+        stack.push((end, prefix));
+    }
+    while let Some((e, p)) = stack.pop() {
+        push(&mut out, pos, e, &p);
+        pos = max(pos, e);
+    }
+    out
 }
 
 fn render(n: Node, c: &mut Chapter) {
     if n.is_text() {
         let text = n.text().unwrap();
+        if c.pre {
+            // tabs line up with the start of the line, not the text node
+            let line = &c.text[c.text.rfind('\n').map_or(0, |i| i + 1)..];
+            let col = line.chars().map(|c| c.width().unwrap_or(0)).sum::<usize>();
+            let text = expand_tabs(text, col.saturating_sub(2), c.opts.tabstop);
+            c.text.push_str(&text.replace('\n', "\n  "));
+            return;
+        }
+        let text = match c.opts.dehyphenate {
+            true => dehyphenate(text, &c.compounds),
+            false => text.to_string(),
+        };
         let content: Vec<_> = text.split_ascii_whitespace().collect();
-
-        if text.starts_with(char::is_whitespace) {
+        // no spaces at the start of a line or doubled up
+        let space =
+            |c: &Chapter| c.pending == 0 && !c.text.is_empty() && !c.text.ends_with(['\n', ' ']);
+        if content.is_empty() {
+            if !text.is_empty() && space(c) {
+                c.text.push(' ');
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && space(c) {
             c.text.push(' ');
         }
-        c.text.push_str(&content.join(" "));
+        c.flush();
+        c.text.push_str(&mem::take(&mut c.opening));
+        let s = sanitize(&content.join(" "));
+        if c.caps {
+            c.small_caps(&s);
+        } else {
+            c.text.push_str(&s);
+        }
         if text.ends_with(char::is_whitespace) {
             c.text.push(' ');
         }
@@ -191,70 +1036,718 @@ fn render(n: Node, c: &mut Chapter) {
     if let Some(id) = n.attribute("id") {
         c.frag.push((id.to_string(), c.text.len()));
     }
+    if let Some(page) = pagebreak(n) {
+        c.pages.push((c.text.len(), page));
+    }
+
+    let (state, color, caps) = (c.state, c.color, c.caps);
+    let style = c.css.style(n);
+    let start = c.text.len();
+    c.style(&style);
+    c.caps = style.small_caps.unwrap_or(caps);
+    if let Some(color) = style.color.filter(|_| c.opts.colors) {
+        c.set_color(color);
+    }
+    element(n, c);
+    c.restore(state);
+    c.set_color(color);
+    c.caps = caps;
+
+    if n.has_attribute("id") {
+        c.targets.push((start, c.text.len()));
+    }
+
+    let align = match n.tag_name().name() {
+        "center" => Some(Align::Center),
+        _ => style.align,
+    };
+    if let Some(align) = align.filter(|_| block(n)) {
+        c.align.push((start, c.text.len(), align));
+    }
+}
+
+// a link to a footnote, marked as one or looking like one, eg [1] or *
+fn noteref(n: Node) -> bool {
+    let marked = n.attributes().iter().any(|a| {
+        (a.name() == "type" && a.value().split_whitespace().any(|t| t == "noteref"))
+            || (a.name() == "role" && a.value() == "doc-noteref")
+    });
+    let text = text(n);
+    let mark = text.trim_matches(|c| "[]()".contains(c));
+    marked
+        || (!mark.is_empty()
+            && mark.chars().count() <= 3
+            && mark
+                .chars()
+                .all(|c| c.is_ascii_digit() || "*†‡§".contains(c)))
+}
+
+// the number of a print page starting here, eg
+// <span epub:type="pagebreak" id="page12" title="12"/>
+fn pagebreak(n: Node) -> Option<String> {
+    let marked = n.attributes().iter().any(|a| {
+        (a.name() == "type" && a.value().split_whitespace().any(|t| t == "pagebreak"))
+            || (a.name() == "role" && a.value() == "doc-pagebreak")
+    });
+    if !marked {
+        return None;
+    }
+    let text = text(n);
+    let label = [n.attribute("title"), n.attribute("aria-label"), Some(&text)]
+        .into_iter()
+        .flatten()
+        .find(|s| !s.is_empty());
+    if let Some(label) = label {
+        return Some(sanitize(label));
+    }
+    // eg id="page12"
+    let id = n.attribute("id").unwrap_or_default();
+    let digits = id.trim_start_matches(|c: char| !c.is_ascii_digit());
+    (!digits.is_empty()).then(|| digits.to_string())
+}
 
+fn scene_break(c: &mut Chapter) {
+    let start = c.open(c.opts.spacing);
+    let text = c.opts.scene_break.clone();
+    c.text.push_str(&sanitize(&text));
+    c.text.push('\n');
+    c.align.push((start, c.text.len(), Align::Center));
+    c.close(c.opts.spacing);
+}
+
+// a paragraph of only ornaments, marking a scene break
+fn asterism(n: Node) -> bool {
+    let text = text(n);
+    !text.is_empty()
+        && text.chars().count() <= 20
+        && !n.descendants().any(|n| n.has_tag_name("img"))
+        && text
+            .chars()
+            .all(|c| c.is_whitespace() || "*⁂✱✲✳✻✼✽❦❧☙#~•·".contains(c))
+}
+
+// blocks that can be aligned. not table cells, which are laid out in columns
+fn block(n: Node) -> bool {
+    matches!(
+        n.tag_name().name(),
+        "p" | "div"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "blockquote"
+            | "li"
+            | "dt"
+            | "dd"
+            | "figure"
+            | "figcaption"
+            | "center"
+            | "section"
+            | "header"
+            | "footer"
+            | "aside"
+            | "article"
+            | "address"
+            | "body"
+    )
+}
+
+fn element(n: Node, c: &mut Chapter) {
     match n.tag_name().name() {
-        "br" => c.text.push('\n'),
-        "hr" => c.text.push_str("\n* * *\n"),
-        "img" => c.text.push_str("\n[IMG]\n"),
+        "br" => {
+            c.flush();
+            c.text.push('\n');
+        }
+        "hr" => scene_break(c),
+        // eg <p>* * *</p> or <p>⁂</p>
+        "div" | "p" if asterism(n) => scene_break(c),
+        "img" => {
+            let start = c.open(0);
+            match n.attribute("alt").map(str::trim) {
+                Some(alt) if !alt.is_empty() => {
+                    let alt: String = alt.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                    c.text.push_str("[IMG: ");
+                    c.text.push_str(&sanitize(&alt));
+                    c.text.push(']');
+                }
+                _ => c.text.push_str("[IMG]"),
+            }
+            if let Some(src) = n.attribute("src") {
+                c.sources.push((start, c.text.len(), src.to_string()));
+            }
+            c.text.push('\n');
+            c.close(0);
+        }
+        "figcaption" => {
+            c.open(0);
+            c.render(n, Attribute::Italic);
+            c.newline();
+            c.close(c.opts.spacing);
+        }
         "a" => {
             match n.attribute("href") {
-                // TODO open external urls in browser
-                Some(url) if !url.starts_with("http") => {
+                // web links too, for the terminal to open
+                Some(url) => {
+                    if c.pending > 0 {
+                        c.flush();
+                    }
                     let start = c.text.len();
-                    c.render(n, Attribute::Underlined, Attribute::NoUnderline);
+                    c.render(n, Attribute::Underlined);
                     c.links.push((start, c.text.len(), url.to_string()));
+                    if !url.starts_with("http") && noteref(n) {
+                        c.notes.push((start, url.to_string()));
+                    }
                 }
                 _ => c.render_text(n),
             }
         }
-        "em" => c.render(n, Attribute::Italic, Attribute::NoItalic),
-        "strong" => c.render(n, Attribute::Bold, Attribute::NormalIntensity),
+        // we add our own parens
+        "rp" => (),
+        // the rtc around it has them
+        "rt" if n.parent().is_some_and(|p| p.has_tag_name("rtc")) => c.render_text(n),
+        "rt" | "rtc" if c.opts.ruby => {
+            c.flush();
+            c.text.push('(');
+            c.render_text(n);
+            c.text.push(')');
+        }
+        "rt" | "rtc" => (),
+        // svg text is placed line by line
+        "text" if svg(n) => {
+            c.open(0);
+            c.render_text(n);
+            c.newline();
+            c.close(0);
+        }
+        "tspan" if svg(n) => {
+            if n.prev_sibling_element().is_some() && !c.text.ends_with([' ', '\n']) {
+                c.text.push(' ');
+            }
+            c.render_text(n);
+        }
+        "title" | "desc" | "defs" | "metadata" | "style" | "script" if svg(n) => (),
+        "s" | "del" | "strike" => c.render(n, Attribute::CrossedOut),
+        "em" => c.render(n, Attribute::Italic),
+        "strong" => c.render(n, Attribute::Bold),
+        // levels look different, to see the outline while skimming
         "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-            c.text.push('\n');
-            c.render(n, Attribute::Bold, Attribute::NormalIntensity);
-            c.text.push('\n');
+            let start = c.open(c.opts.spacing);
+            match n.tag_name().name() {
+                "h1" | "h2" => c.render(n, Attribute::Bold),
+                "h3" => c.render(n, Attribute::Italic),
+                name => {
+                    c.text.push_str(match name {
+                        "h4" => "▸ ",
+                        "h5" => "▹ ",
+                        _ => "· ",
+                    });
+                    c.render_text(n);
+                }
+            }
+            if n.has_tag_name("h1") && c.css.style(n).align.is_none() {
+                c.align.push((start, c.text.len(), Align::Center));
+            }
+            c.newline();
+            c.blocks.push((start, c.text.len()));
+            c.close(c.opts.spacing);
         }
-        "blockquote" | "div" | "p" | "tr" => {
-            // TODO compress newlines
-            c.text.push('\n');
+        "blockquote" => {
+            let quote = c.opts.quote.clone();
+            c.indented(n, &quote, c.opts.italic_quotes, c.opts.spacing);
+        }
+        "p" if c.opts.indent => {
+            let start = c.open(0);
+            c.text.push_str("  ");
             c.render_text(n);
-            c.text.push('\n');
+            c.newline();
+            c.blocks.push((start, c.text.len()));
+            c.close(0);
+        }
+        "div" | "p" | "tr" => {
+            let start = c.open(c.opts.spacing);
+            c.render_text(n);
+            c.newline();
+            c.blocks.push((start, c.text.len()));
+            c.close(c.opts.spacing);
+        }
+        "table" => {
+            if !table(n, c) {
+                c.render_text(n);
+            }
+        }
+        "ol" | "ul" => {
+            let next = if n.has_tag_name("ol") {
+                let start = n.attribute("start").and_then(|s| s.trim().parse().ok());
+                Some(start.unwrap_or(1))
+            } else {
+                None
+            };
+            // nested lists line up with the text of their item
+            let nested = !c.lists.is_empty();
+            c.lists.push(next);
+            if nested {
+                c.indented(n, "  ", false, 0);
+            } else {
+                c.open(c.opts.spacing);
+                c.render_text(n);
+                c.close(c.opts.spacing);
+            }
+            c.lists.pop();
+        }
+        "dl" => {
+            c.open(c.opts.spacing);
+            c.render_text(n);
+            c.close(c.opts.spacing);
         }
+        "dt" => {
+            let start = c.open(0);
+            c.render(n, Attribute::Bold);
+            c.newline();
+            c.blocks.push((start, c.text.len()));
+            c.close(0);
+        }
+        "dd" => c.indented(n, "    ", false, 0),
         "li" => {
-            c.text.push_str("\n- ");
+            c.open(0);
+            match c.lists.last_mut() {
+                Some(Some(next)) => {
+                    if let Some(value) = n.attribute("value").and_then(|s| s.trim().parse().ok()) {
+                        *next = value;
+                    }
+                    c.text.push_str(&format!("{}. ", next));
+                    *next += 1;
+                }
+                _ => c.text.push_str("- "),
+            }
+            let start = c.text.len();
             c.render_text(n);
-            c.text.push('\n');
+            c.newline();
+            c.blocks.push((start, c.text.len()));
+            c.close(0);
         }
-        "pre" => {
-            c.text.push_str("\n  ");
-            n
-                .descendants()
-                .filter(Node::is_text)
-                .map(|n| n.text().unwrap().replace('\n', "\n  "))
-                .for_each(|s| c.text.push_str(&s));
-            c.text.push('\n');
+        "pre" => pre(n, c),
+        "math" => {
+            let mut s = math(n);
+            if s.is_empty() {
+                s = n.attribute("alttext").unwrap_or_default().to_string();
+            }
+            let s = sanitize(&s.split_whitespace().collect::<Vec<_>>().join(" "));
+            if n.attribute("display") == Some("block") {
+                c.open(c.opts.spacing);
+                c.text.push_str(&s);
+                c.newline();
+                c.close(c.opts.spacing);
+            } else {
+                c.flush();
+                c.text.push_str(&s);
+            }
+        }
+        // code blocks without a surrounding pre. inline code reflows
+        "code"
+            if !c.pre
+                && c.css.style(n).block == Some(true)
+                && n.descendants()
+                    .any(|n| n.text().is_some_and(|t| t.contains('\n'))) =>
+        {
+            pre(n, c)
         }
         _ => c.render_text(n),
     }
 }
 
-fn epub2(doc: Document, nav: &mut HashMap<String, String>) {
+fn svg(n: Node) -> bool {
+    n.tag_name().namespace() == Some("http://www.w3.org/2000/svg")
+}
+
+// hyphenated words like well-known, lowercased
+fn compounds(n: Node) -> HashSet<String> {
+    n.descendants()
+        .filter(Node::is_text)
+        .flat_map(|n| n.text().unwrap().split_whitespace())
+        .map(|word| word.trim_matches(|c: char| !c.is_alphabetic()))
+        .filter(|word| word.contains('-'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// rejoin words split at the end of a source line, eg exam-\nple, unless the
+// book hyphenates them elsewhere
+fn dehyphenate(text: &str, compounds: &HashSet<String>) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('-') {
+        let (head, tail) = (&rest[..i], &rest[i + 1..]);
+        let gap = tail.len() - tail.trim_start().len();
+        let next = tail[gap..]
+            .split(|c: char| !c.is_alphabetic())
+            .next()
+            .unwrap();
+        let prev = head.rsplit(|c: char| !c.is_alphabetic()).next().unwrap();
+        s.push_str(head);
+        if prev.is_empty() || !tail[..gap].contains('\n') || !next.starts_with(char::is_lowercase) {
+            s.push('-');
+            rest = tail;
+            continue;
+        }
+        if compounds.contains(&format!("{}-{}", prev, next).to_lowercase()) {
+            s.push('-');
+        }
+        rest = &tail[gap..];
+    }
+    s.push_str(rest);
+    s
+}
+
+// mathml as a line of text, eg x² + √(y + 1) = (a + b)/2
+fn math(n: Node) -> String {
+    let args: Vec<_> = n.children().filter(Node::is_element).map(math).collect();
+    let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+    match n.tag_name().name() {
+        "mi" | "mn" | "mtext" | "ms" => text(n),
+        "mo" => {
+            let op = text(n);
+            // unary, eg -x
+            if n.prev_sibling_element().is_none() {
+                return op;
+            }
+            match op.as_str() {
+                "=" | "+" | "-" | "−" | "±" | "×" | "÷" | "<" | ">" | "≤" | "≥" | "≠" | "≈"
+                | "≡" | "→" | "⇒" | "∈" | "⊂" | "∪" | "∩" => format!(" {} ", op),
+                "," | ";" => format!("{} ", op),
+                _ => op,
+            }
+        }
+        "mspace" => " ".into(),
+        "mfrac" => format!("{}/{}", group(&arg(0)), group(&arg(1))),
+        "msqrt" => format!("√{}", group(&args.concat())),
+        "mroot" => format!("{}√{}", script(&arg(1), '^'), group(&arg(0))),
+        "msup" => format!("{}{}", arg(0), script(&arg(1), '^')),
+        "msub" => format!("{}{}", arg(0), script(&arg(1), '_')),
+        "msubsup" => format!("{}{}{}", arg(0), script(&arg(1), '_'), script(&arg(2), '^')),
+        // limits, eg ∑ᵢ, spaced from what they apply to
+        "mover" => format!("{}{} ", arg(0), script(&arg(1), '^')),
+        "munder" => format!("{}{} ", arg(0), script(&arg(1), '_')),
+        "munderover" => {
+            format!(
+                "{}{}{} ",
+                arg(0),
+                script(&arg(1), '_'),
+                script(&arg(2), '^')
+            )
+        }
+        "mfenced" => {
+            let open = n.attribute("open").unwrap_or("(");
+            let close = n.attribute("close").unwrap_or(")");
+            let sep = n.attribute("separators").unwrap_or(",").trim();
+            let sep = sep
+                .chars()
+                .next()
+                .map_or(String::new(), |c| format!("{} ", c));
+            format!("{}{}{}", open, args.join(&sep), close)
+        }
+        "mtable" => format!("[{}]", args.join("; ")),
+        "mtr" | "mlabeledtr" => args.join(" "),
+        // the first child is the presentation, the rest annotations
+        "semantics" => arg(0),
+        "annotation" | "annotation-xml" | "none" | "mprescripts" => String::new(),
+        _ => args.concat(),
+    }
+}
+
+fn text(n: Node) -> String {
+    n.descendants()
+        .filter_map(|n| n.text().filter(|_| n.is_text()))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// parens around anything longer than a number or a name
+fn group(s: &str) -> String {
+    let s = s.trim();
+    if s.chars().count() <= 1 || s.chars().all(|c| c.is_alphanumeric() || c == '.') {
+        s.to_string()
+    } else {
+        format!("({})", s)
+    }
+}
+
+// a superscript (^) or subscript (_), in unicode if it has the characters
+fn script(s: &str, kind: char) -> String {
+    let (from, to) = match kind {
+        '^' => ("0123456789+-−=()in", "⁰¹²³⁴⁵⁶⁷⁸⁹⁺⁻⁻⁼⁽⁾ⁱⁿ"),
+        _ => (
+            "0123456789+-−=()aehijklmnoprstuvx",
+            "₀₁₂₃₄₅₆₇₈₉₊₋₋₌₍₎ₐₑₕᵢⱼₖₗₘₙₒₚᵣₛₜᵤᵥₓ",
+        ),
+    };
+    let s = s.trim();
+    let small: Option<String> = s
+        .chars()
+        .map(|c| {
+            from.chars()
+                .position(|f| f == c)
+                .and_then(|i| to.chars().nth(i))
+        })
+        .collect();
+    match small {
+        Some(small) if !s.is_empty() => small,
+        _ if s.is_empty() => String::new(),
+        _ => format!("{}{}", kind, group(s)),
+    }
+}
+
+// keep the whitespace and markup of a code block, indented and scrolled instead of wrapped
+fn pre(n: Node, c: &mut Chapter) {
+    if c.pre {
+        return c.render_text(n);
+    }
+    let start = c.open(c.opts.spacing);
+    c.text.push_str("  ");
+    c.pre = true;
+    c.render_text(n);
+    c.pre = false;
+
+    // a newline right after <pre> is ignored, as are trailing blank lines
+    let skip = if c.text[start..].starts_with("  \n") {
+        3
+    } else {
+        0
+    };
+    let end = start + c.text[start + skip..].trim_end().len();
+    c.text.replace_range(start..start + skip, "");
+    c.text.truncate(end);
+    c.remap(|pos| match pos > start {
+        true => min(max(pos, start + skip) - skip, end),
+        false => pos,
+    });
+    c.text.push('\n');
+    c.nowrap.push((start, c.text.len()));
+    c.close(c.opts.spacing);
+}
+
+// a table cell's text on one line, with its links and ids by where they are
+// in it
+#[derive(Default)]
+struct Td {
+    text: String,
+    links: Vec<(usize, usize, String)>,
+    frag: Vec<(String, usize)>,
+}
+
+impl Td {
+    fn new(n: Node) -> Self {
+        let mut raw = String::new();
+        let mut links = Vec::new();
+        let mut frag = Vec::new();
+        for n in n.descendants() {
+            if let Some(id) = n.attribute("id") {
+                frag.push((id.to_string(), raw.len()));
+            }
+            if let (true, Some(url)) = (n.has_tag_name("a"), n.attribute("href")) {
+                let len: usize = n
+                    .descendants()
+                    .filter(Node::is_text)
+                    .map(|n| n.text().unwrap().len())
+                    .sum();
+                links.push((raw.len(), raw.len() + len, url.to_string()));
+            }
+            if n.is_text() {
+                raw.push_str(n.text().unwrap());
+            }
+        }
+        // whitespace collapsed, with where each byte of raw went
+        let mut text = String::new();
+        let mut at = vec![0; raw.len() + 1];
+        let mut space = false;
+        for (i, c) in raw.char_indices() {
+            if c.is_whitespace() {
+                space = !text.is_empty();
+            } else if let Some(c) = visible(c) {
+                if space {
+                    text.push(' ');
+                    space = false;
+                }
+                at[i] = text.len();
+                text.push(c);
+                continue;
+            }
+            at[i] = text.len();
+        }
+        at[raw.len()] = text.len();
+        Td {
+            links: links
+                .into_iter()
+                .map(|(a, b, url)| (at[a], at[b], url))
+                .filter(|l| l.0 < l.1)
+                .collect(),
+            frag: frag.into_iter().map(|(id, i)| (id, at[i])).collect(),
+            text,
+        }
+    }
+}
+
+// lay out a table with box drawing characters. false if it's empty
+fn table(n: Node, c: &mut Chapter) -> bool {
+    let rows: Vec<(bool, Option<&str>, Vec<Td>)> = n
+        .descendants()
+        .filter(|n| n.has_tag_name("tr"))
+        // skip nested tables
+        .filter(|tr| tr.ancestors().find(|a| a.has_tag_name("table")) == Some(n))
+        .map(|tr| {
+            let cells = tr
+                .children()
+                .filter(|n| n.has_tag_name("td") || n.has_tag_name("th"));
+            let header = cells.clone().all(|n| n.has_tag_name("th"));
+            (header, tr.attribute("id"), cells.map(Td::new).collect())
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = Vec::new();
+    for (_, _, cells) in &rows {
+        for (i, cell) in cells.iter().map(|c| &c.text).enumerate() {
+            let w = cell.chars().map(|c| c.width().unwrap_or(0)).sum();
+            match widths.get_mut(i) {
+                Some(max_w) => *max_w = max(*max_w, w),
+                None => widths.push(w),
+            }
+        }
+    }
+    if widths.is_empty() {
+        return false;
+    }
+
+    let rule = |left: &str, mid: &str, right: &str| {
+        let cols: Vec<String> = widths.iter().map(|&w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}\n", left, cols.join(mid), right)
+    };
+    let start = c.open(c.opts.spacing);
+    c.text.push_str(&rule("┌", "┬", "┐"));
+    let len = rows.len();
+    for (i, (header, id, cells)) in rows.into_iter().enumerate() {
+        if let Some(id) = id {
+            c.frag.push((id.to_string(), c.text.len()));
+        }
+        c.text.push('│');
+        let mut cells = cells.into_iter();
+        for &w in &widths {
+            let cell = cells.next().unwrap_or_default();
+            let pad = w - cell
+                .text
+                .chars()
+                .map(|c| c.width().unwrap_or(0))
+                .sum::<usize>();
+            c.text.push(' ');
+            let at = c.text.len();
+            let links = cell
+                .links
+                .into_iter()
+                .map(|(a, b, url)| (at + a, at + b, url));
+            c.links.extend(links);
+            c.frag
+                .extend(cell.frag.into_iter().map(|(id, i)| (id, at + i)));
+            c.text.push_str(&cell.text);
+            c.text.extend(iter::repeat_n(' ', pad + 1));
+            c.text.push('│');
+        }
+        c.text.push('\n');
+        if header && i + 1 < len {
+            c.text.push_str(&rule("├", "┼", "┤"));
+        }
+    }
+    c.text.push_str(&rule("└", "┴", "┘"));
+    c.blocks.push((start, c.text.len()));
+    let total = widths.iter().sum::<usize>() + 3 * widths.len() + 1;
+    c.tables.push((start, c.text.len(), total));
+    c.close(c.opts.spacing);
+    true
+}
+
+// all text from the book passes through here, so that it can't
+// spoof the ui or leave the terminal in a weird state
+pub fn sanitize(text: &str) -> String {
+    text.chars().filter_map(visible).collect()
+}
+
+fn visible(c: char) -> Option<char> {
+    match c {
+        // control characters as symbols
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32),
+        '\x7f' => Some('\u{2421}'),
+        // C1 controls, eg a single byte CSI
+        '\u{80}'..='\u{9f}' => Some(char::REPLACEMENT_CHARACTER),
+        // bidi embeddings, overrides and isolates
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => None,
+        // line and paragraph separators
+        '\u{2028}' | '\u{2029}' => Some(' '),
+        // interlinear annotations
+        '\u{fff9}'..='\u{fffb}' => None,
+        _ => Some(c),
+    }
+}
+
+// col is where the text starts on its line
+fn expand_tabs(text: &str, mut col: usize, tabstop: usize) -> String {
+    let mut s = String::new();
+    for c in text.chars() {
+        match c {
+            '\r' => (),
+            '\t' => {
+                let n = tabstop - col % tabstop;
+                s.extend(iter::repeat_n(' ', n));
+                col += n;
+            }
+            '\n' => {
+                s.push(c);
+                col = 0;
+            }
+            _ => {
+                if let Some(c) = visible(c) {
+                    s.push(c);
+                    col += c.width().unwrap_or(0);
+                }
+            }
+        }
+    }
+    s
+}
+
+// resolve a relative url against the zip path of the file it appears in
+fn resolve(base: &str, url: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in url.split('#').next().unwrap().split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => (),
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn epub2(
+    doc: Document,
+    nav: &mut HashMap<String, String>,
+    sections: &mut Vec<(usize, String, String)>,
+) {
     doc.descendants()
         .find(|n| n.has_tag_name("navMap"))
         .unwrap()
         .descendants()
         .filter(|n| n.has_tag_name("navPoint"))
         .for_each(|n| {
-            let path = n
+            let src = n
                 .descendants()
                 .find(|n| n.has_tag_name("content"))
                 .unwrap()
                 .attribute("src")
-                .unwrap()
-                .split('#')
-                .next()
-                .unwrap()
-                .to_string();
+                .unwrap();
+            let path = src.split('#').next().unwrap().to_string();
             let text = n
                 .descendants()
                 .find(|n| n.has_tag_name("text"))
@@ -262,11 +1755,17 @@ fn epub2(doc: Document, nav: &mut HashMap<String, String>) {
                 .text()
                 .unwrap()
                 .to_string();
-            // TODO subsections
+            let depth = n.ancestors().filter(|n| n.has_tag_name("navPoint")).count() - 1;
+            let title = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            sections.push((depth, src.to_string(), sanitize(&title)));
             nav.entry(path).or_insert(text);
         });
 }
-fn epub3(doc: Document, nav: &mut HashMap<String, String>) {
+fn epub3(
+    doc: Document,
+    nav: &mut HashMap<String, String>,
+    sections: &mut Vec<(usize, String, String)>,
+) {
     doc.descendants()
         .find(|n| n.has_tag_name("nav"))
         .unwrap()
@@ -276,18 +1775,16 @@ fn epub3(doc: Document, nav: &mut HashMap<String, String>) {
         .descendants()
         .filter(|n| n.has_tag_name("a"))
         .for_each(|n| {
-            let path = n
-                .attribute("href")
-                .unwrap()
-                .split('#')
-                .next()
-                .unwrap()
-                .to_string();
-            let text = n
+            let href = n.attribute("href").unwrap();
+            let path = href.split('#').next().unwrap().to_string();
+            let text: String = n
                 .descendants()
                 .filter(Node::is_text)
                 .map(|n| n.text().unwrap())
                 .collect();
+            let depth = n.ancestors().filter(|n| n.has_tag_name("ol")).count() - 1;
+            let title = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            sections.push((depth, href.to_string(), sanitize(&title)));
             nav.insert(path, text);
         });
 }
@@ -0,0 +1,93 @@
+// ratings and reviews for other sites: goodreads' import csv, and json
+pub struct Row {
+    pub title: String,
+    pub author: String,
+    pub identifier: String,
+    pub rating: u8,
+    pub review: String,
+}
+
+// a word looked up, for anki's import as a card
+pub struct Word {
+    pub word: String,
+    pub definition: String,
+    pub title: String,
+}
+
+// goodreads matches on the isbn, eg urn:isbn:978-0-14-044913-6
+fn isbn(identifier: &str) -> String {
+    let s = identifier.to_ascii_lowercase();
+    let s = s.trim_start_matches("urn:").trim_start_matches("isbn:");
+    let isbn: String = s.chars().filter(|&c| c != '-' && c != ' ').collect();
+    let digits = isbn.trim_end_matches('x');
+    if (isbn.len() == 10 || isbn.len() == 13) && digits.chars().all(|c| c.is_ascii_digit()) {
+        isbn.to_uppercase()
+    } else {
+        String::new()
+    }
+}
+
+pub fn csv(rows: &[Row]) -> String {
+    // quoted, with quotes doubled. reviews may span lines
+    let field = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    let mut s = String::from("Title,Author,ISBN,My Rating,My Review\n");
+    for row in rows {
+        let fields = [
+            field(&row.title),
+            field(&row.author),
+            field(&isbn(&row.identifier)),
+            row.rating.to_string(),
+            field(&row.review),
+        ];
+        s.push_str(&fields.join(","));
+        s.push('\n');
+    }
+    s
+}
+
+pub fn json(rows: &[Row]) -> String {
+    let string = |s: &str| {
+        let mut out = String::from('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+        out
+    };
+    let books: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "  {{\"title\": {}, \"author\": {}, \"identifier\": {}, \"isbn\": {}, \"rating\": {}, \"review\": {}}}",
+                string(&row.title),
+                string(&row.author),
+                string(&row.identifier),
+                string(&isbn(&row.identifier)),
+                row.rating,
+                string(&row.review)
+            )
+        })
+        .collect();
+    if books.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", books.join(",\n"))
+}
+
+// one card a line, fields split by tabs, which anki takes as they are
+pub fn tsv(words: &[Word]) -> String {
+    let field = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    let mut s = String::new();
+    for w in words {
+        let fields = [field(&w.word), field(&w.definition), field(&w.title)];
+        s.push_str(&fields.join("\t"));
+        s.push('\n');
+    }
+    s
+}
@@ -0,0 +1,113 @@
+// search that ignores accents and ligatures: Zoe finds Zoë, oe finds œ.
+// the query becomes a regex matching every spelling, so matches are found
+// and highlighted in the text as it is
+use std::{collections::HashMap, sync::OnceLock};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// letters that don't decompose but are read as others
+const EXTRA: [(char, &str); 12] = [
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('æ', "ae"),
+    ('Æ', "AE"),
+    ('ß', "ss"),
+    ('ø', "o"),
+    ('Ø', "O"),
+    ('ł', "l"),
+    ('Ł', "L"),
+    ('đ', "d"),
+    ('Đ', "D"),
+    ('ı', "i"),
+];
+
+// latin, greek, cyrillic and the ligatures
+const RANGES: [(u32, u32); 5] = [
+    (0xc0, 0x24f),
+    (0x370, 0x3ff),
+    (0x400, 0x4ff),
+    (0x1e00, 0x1fff),
+    (0xfb00, 0xfb06),
+];
+
+// a letter without its accents, eg é to e, ﬁ to fi
+fn base(c: char) -> String {
+    if let Some((_, s)) = EXTRA.iter().find(|e| e.0 == c) {
+        return s.to_string();
+    }
+    c.to_string()
+        .nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .collect()
+}
+
+// the other spellings of each letter, and of each pair like oe
+fn spellings() -> &'static HashMap<String, Vec<char>> {
+    static TABLE: OnceLock<HashMap<String, Vec<char>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table: HashMap<String, Vec<char>> = HashMap::new();
+        let chars = RANGES
+            .iter()
+            .flat_map(|&(a, b)| a..=b)
+            .filter_map(char::from_u32);
+        for c in chars {
+            let base = base(c);
+            if !base.is_empty() && base != c.to_string() {
+                table.entry(base).or_default().push(c);
+            }
+        }
+        table
+    })
+}
+
+// a regex for the literal query in any spelling
+pub fn pattern(query: &str) -> String {
+    let table = spellings();
+    let chars: Vec<char> = query
+        .chars()
+        .flat_map(|c| base(c).chars().collect::<Vec<_>>())
+        .collect();
+    // a letter in any spelling, the accents maybe apart, maybe followed by a
+    // soft hyphen
+    let letter = |c: char| match table.get(&c.to_string()) {
+        Some(others) if c.is_alphabetic() => {
+            let others: String = others.iter().collect();
+            format!("[{}{}]\\p{{M}}*\u{ad}?", c, others)
+        }
+        _ if c.is_alphabetic() => format!("{}\u{ad}?", c),
+        _ => regex::escape(&c.to_string()),
+    };
+    let mut s = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        // any run of spaces and line breaks between words
+        if chars[i].is_whitespace() {
+            s.push_str("\\s+");
+            while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+                i += 1;
+            }
+            continue;
+        }
+        // a pair written as one, eg oe as œ
+        let pair = chars.get(i..i + 2).and_then(|p| {
+            let p: String = p.iter().collect();
+            table.get(&p)
+        });
+        match pair {
+            Some(ligatures) => {
+                let ligatures: String = ligatures.iter().collect();
+                s.push_str(&format!(
+                    "(?:{}{}|[{}])",
+                    letter(chars[i]),
+                    letter(chars[i + 1]),
+                    ligatures
+                ));
+                i += 2;
+            }
+            None => {
+                s.push_str(&letter(chars[i]));
+                i += 1;
+            }
+        }
+    }
+    s
+}
@@ -0,0 +1,168 @@
+use image::{imageops::FilterType, ImageFormat};
+use std::env;
+
+use crate::term;
+
+// https://sw.kovidgoyal.net/kitty/graphics-protocol/
+const CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+}
+
+pub fn detect() -> Option<Protocol> {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    if env::var("KITTY_WINDOW_ID").is_ok()
+        || term == "xterm-kitty"
+        || term == "xterm-ghostty"
+        || program == "WezTerm"
+        || program == "ghostty"
+    {
+        return Some(Protocol::Kitty);
+    }
+    // primary device attributes, eg \x1b[?62;4;22c. 4 means sixel
+    let (_, reply) = term::query("")?;
+    let params = reply.trim_start_matches("\x1b[?").trim_end_matches('c');
+    if params.split(';').any(|p| p == "4") {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+// send png data once, to be placed by id
+pub fn transmit(id: usize, png: &[u8]) -> String {
+    let data = base64::encode(png);
+    let mut chunks = data.as_bytes().chunks(CHUNK).peekable();
+    let mut s = String::new();
+    let mut first = true;
+    while let Some(chunk) = chunks.next() {
+        let more = chunks.peek().is_some() as u8;
+        // q=2 suppresses responses, which would show up as input
+        if first {
+            s.push_str(&format!("\x1b_Ga=t,q=2,f=100,i={},m={};", id, more));
+            first = false;
+        } else {
+            s.push_str(&format!("\x1b_Gq=2,m={};", more));
+        }
+        s.push_str(std::str::from_utf8(chunk).unwrap());
+        s.push_str("\x1b\\");
+    }
+    s
+}
+
+// place the pixel rows y..y + h of an image over cols x rows cells
+pub fn place(id: usize, cols: usize, rows: usize, y: u32, h: u32) -> String {
+    format!(
+        "\x1b_Ga=p,q=2,i={},C=1,c={},r={},y={},h={}\x1b\\",
+        id, cols, rows, y, h
+    )
+}
+
+pub fn clear() -> &'static str {
+    "\x1b_Ga=d,d=A,q=2\x1b\\"
+}
+
+const TRANSPARENT: u8 = u8::MAX;
+
+pub struct Sixel {
+    pub cols: usize,
+    pub rows: usize,
+    pub cell: (usize, usize),
+    width: usize,
+    height: usize,
+    // indexes into a 6x6x6 color cube
+    pixels: Vec<u8>,
+}
+
+impl Sixel {
+    // scale the png to fit in cols x rows cells
+    pub fn new(png: &[u8], cols: usize, rows: usize, cell: (usize, usize)) -> Option<Self> {
+        let img = image::load_from_memory_with_format(png, ImageFormat::Png)
+            .ok()?
+            .to_rgba8();
+        let scale = f64::min(
+            (cols * cell.0) as f64 / img.width() as f64,
+            (rows * cell.1) as f64 / img.height() as f64,
+        );
+        let width = ((img.width() as f64 * scale) as u32).max(1);
+        let height = ((img.height() as f64 * scale) as u32).max(1);
+        let img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+        let level = |v: u8| (v as u16 * 5 + 127) / 255;
+        let pixels = img
+            .pixels()
+            .map(|p| {
+                if p[3] < 128 {
+                    TRANSPARENT
+                } else {
+                    (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as u8
+                }
+            })
+            .collect();
+        Some(Sixel {
+            cols,
+            rows,
+            cell,
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        })
+    }
+    // encode pixel rows y..y + h
+    pub fn encode(&self, y: usize, h: usize) -> String {
+        let end = (y + h).min(self.height);
+        // transparent background, 1:1 aspect
+        let mut s = format!("\x1bP0;1;0q\"1;1;{};{}", self.width, end.saturating_sub(y));
+        let mut defined = [false; 216];
+        let mut bands: Vec<Option<Vec<u8>>> = vec![None; 216];
+        for top in (y..end).step_by(6) {
+            for k in 0..6.min(end - top) {
+                let row = &self.pixels[(top + k) * self.width..][..self.width];
+                for (x, &p) in row.iter().enumerate() {
+                    if p != TRANSPARENT {
+                        let band = bands[p as usize].get_or_insert_with(|| vec![0; self.width]);
+                        band[x] |= 1 << k;
+                    }
+                }
+            }
+            for (color, band) in bands.iter_mut().enumerate() {
+                let band = match band.take() {
+                    Some(band) => band,
+                    None => continue,
+                };
+                if !defined[color] {
+                    defined[color] = true;
+                    let pct = |v: usize| v * 100 / 5;
+                    s.push_str(&format!(
+                        "#{};2;{};{};{}",
+                        color,
+                        pct(color / 36),
+                        pct(color / 6 % 6),
+                        pct(color % 6)
+                    ));
+                }
+                s.push_str(&format!("#{}", color));
+                // run length encoding
+                let mut i = 0;
+                while i < band.len() {
+                    let bits = band[i];
+                    let run = band[i..].iter().take_while(|&&b| b == bits).count();
+                    let c = (63 + bits) as char;
+                    if run > 3 {
+                        s.push_str(&format!("!{}{}", run, c));
+                    } else {
+                        s.extend(std::iter::repeat_n(c, run));
+                    }
+                    i += run;
+                }
+                s.push('$');
+            }
+            s.push('-');
+        }
+        s.push_str("\x1b\\");
+        s
+    }
+}
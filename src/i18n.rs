@@ -0,0 +1,385 @@
+// ui text in the reader's language
+use std::env;
+
+// keys in the help, a blank line between groups
+pub const KEYS: [&str; 44] = [
+    "Esc q",
+    "Fn",
+    "Tab",
+    "i",
+    "r",
+    "o",
+    "p",
+    "t",
+    "P",
+    "M",
+    ":",
+    "",
+    "PageDown Right Space f l",
+    "PageUp Left b h",
+    "d",
+    "u",
+    "Down j",
+    "Up k",
+    "Home g",
+    "End G",
+    "50%",
+    "1234G",
+    "12j",
+    "[",
+    "]",
+    "< >",
+    "- +",
+    "c",
+    "",
+    "/",
+    "?",
+    "n",
+    "N",
+    "mx",
+    "'x",
+    "B",
+    "A",
+    "W",
+    "F",
+    "Shift-Tab",
+    "y",
+    "Backspace Ctrl-o",
+    "Enter",
+    "H",
+];
+
+pub struct Text {
+    // what each of KEYS does
+    pub help: [&'static str; KEYS.len()],
+    pub chapter: &'static str,
+    pub total: &'static str,
+    pub page: &'static str,
+    pub location: &'static str,
+    pub section: &'static str,
+    pub not_found: &'static str,
+    // before the search prompt with --regex, or after Tab
+    pub regex: &'static str,
+    pub mark_set: &'static str,
+    pub no_mark: &'static str,
+    pub mark_deleted: &'static str,
+    // under the marks, after m, m Delete and '
+    pub mark_prompt: &'static str,
+    pub unmark_prompt: &'static str,
+    pub jump_prompt: &'static str,
+    pub no_command: &'static str,
+    pub copied: &'static str,
+    pub no_link: &'static str,
+    pub no_browser: &'static str,
+    pub no_jumps: &'static str,
+    pub bookmarked: &'static str,
+    pub bookmarks_keys: &'static str,
+    pub annotated: &'static str,
+    pub annotations_keys: &'static str,
+    pub no_dictionary: &'static str,
+    pub words_keys: &'static str,
+    pub reanchored: &'static str,
+    pub mouse_on: &'static str,
+    pub mouse_off: &'static str,
+    pub rating: &'static str,
+    pub rating_keys: &'static str,
+    // on opening a book bigger than --budget, with the MB needed
+    pub warning: &'static str,
+    pub over_budget: &'static str,
+    pub images_skipped: &'static str,
+    // asked on opening a copy of a book read at another path
+    pub same_book: &'static str,
+    pub continue_there: &'static str,
+    pub merge_there: &'static str,
+    // the answer to them that says yes
+    pub yes: &'static str,
+    pub review: &'static str,
+    pub review_keys: &'static str,
+    // eg match 12/87 in chapter, 240 in book
+    pub matched: &'static str,
+    pub in_chapter: &'static str,
+    pub in_book: &'static str,
+}
+
+static EN: Text = Text {
+    help: [
+        "Quit",
+        "Help",
+        "Table of Contents",
+        "Progress and Metadata",
+        "Rate and review",
+        "Footnotes on the page",
+        "Progress gauge",
+        "Next color theme",
+        "Plain text, without bold or italics",
+        "Mouse on or off, to select text",
+        "Command line, eg :theme sepia, :set width=80",
+        "",
+        "Page Down",
+        "Page Up",
+        "Half Page Down",
+        "Half Page Up",
+        "Line Down",
+        "Line Up",
+        "Chapter Start",
+        "Chapter End",
+        "Go to that percentage of the book",
+        "Go to location 1234, as in Metadata",
+        "Repeat 12 times, eg 5] 3f",
+        "Previous Chapter",
+        "Next Chapter",
+        "Scroll Tables and Code",
+        "Narrower or wider text",
+        "Text centered or on the left",
+        "",
+        "Search Forward",
+        "Search Backward",
+        "Repeat search forward",
+        "Repeat search backward",
+        "Set mark x",
+        "Jump to mark x",
+        "Bookmarks, named and kept with the book",
+        "Annotations, added with :annotate and a note",
+        "Words looked up, with :define",
+        "Follow a link by its label",
+        "Select a link, then Tab to the next and Enter to follow",
+        "Copy the selected link",
+        "Back to before the last jump, eg a link",
+        "Forward again",
+        "Jump list, to go back to any place in it",
+    ],
+    chapter: "chapter",
+    total: "total",
+    page: "print page",
+    location: "location",
+    section: "section",
+    not_found: "Not found",
+    regex: "regex",
+    mark_set: "Mark set",
+    no_mark: "No mark",
+    mark_deleted: "Mark deleted",
+    mark_prompt: "Set mark, Delete to delete one:",
+    unmark_prompt: "Delete mark:",
+    jump_prompt: "Jump to mark:",
+    no_command: "Not a command",
+    copied: "Copied",
+    no_link: "No link selected, Shift-Tab selects one",
+    no_browser: "Couldn't open",
+    no_jumps: "No jumps yet",
+    bookmarked: "Bookmarked",
+    bookmarks_keys: "Enter jumps, a adds one here, d deletes",
+    annotated: "Annotated",
+    annotations_keys: "Enter jumps, a adds one, d deletes, ✗ not found in the book",
+    no_dictionary: "Couldn't look up with",
+    words_keys: "Enter jumps, a looks one up, d deletes",
+    reanchored: "Found again",
+    mouse_on: "Mouse on",
+    mouse_off: "Mouse off",
+    rating: "rating",
+    rating_keys: "Left/Right, 0-5",
+    warning: "warning",
+    over_budget: "over the --budget, images and colors skipped",
+    images_skipped: "images skipped, over the --budget",
+    same_book: "same book as",
+    continue_there: "continue from there? [y/N]",
+    merge_there: "merge what was read there? [y/N]",
+    yes: "y",
+    review: "review",
+    review_keys: "Enter to edit",
+    matched: "match",
+    in_chapter: "in chapter",
+    in_book: "in book",
+};
+
+static DE: Text = Text {
+    help: [
+        "Beenden",
+        "Hilfe",
+        "Inhaltsverzeichnis",
+        "Fortschritt und Metadaten",
+        "Bewerten und rezensieren",
+        "Fußnoten auf der Seite",
+        "Fortschrittsbalken",
+        "Nächstes Farbschema",
+        "Schlichter Text, ohne Fett oder Kursiv",
+        "Maus an oder aus, um Text zu markieren",
+        "Befehlszeile, zB :theme sepia, :set width=80",
+        "",
+        "Seite vor",
+        "Seite zurück",
+        "Halbe Seite vor",
+        "Halbe Seite zurück",
+        "Zeile vor",
+        "Zeile zurück",
+        "Kapitelanfang",
+        "Kapitelende",
+        "Zu diesem Prozentsatz des Buchs",
+        "Zu Position 1234, wie in den Metadaten",
+        "12 mal wiederholen, zB 5] 3f",
+        "Vorheriges Kapitel",
+        "Nächstes Kapitel",
+        "Tabellen und Code scrollen",
+        "Text schmaler oder breiter",
+        "Text mittig oder links",
+        "",
+        "Vorwärts suchen",
+        "Rückwärts suchen",
+        "Suche vorwärts wiederholen",
+        "Suche rückwärts wiederholen",
+        "Marke x setzen",
+        "Zu Marke x springen",
+        "Lesezeichen, benannt und beim Buch gespeichert",
+        "Anmerkungen, mit :annotate und einer Notiz hinzugefügt",
+        "Nachgeschlagene Wörter, mit :define",
+        "Link über seine Marke folgen",
+        "Link wählen, dann Tab zum nächsten und Enter zum Folgen",
+        "Gewählten Link kopieren",
+        "Zurück vor den letzten Sprung, zB einen Link",
+        "Wieder vorwärts",
+        "Sprungliste, um zu jeder Stelle darin zurückzukehren",
+    ],
+    chapter: "Kapitel",
+    total: "gesamt",
+    page: "Druckseite",
+    location: "Position",
+    section: "Abschnitt",
+    not_found: "Nicht gefunden",
+    regex: "Regex",
+    mark_set: "Marke gesetzt",
+    no_mark: "Keine Marke",
+    mark_deleted: "Marke gelöscht",
+    mark_prompt: "Marke setzen, Entf zum Löschen:",
+    unmark_prompt: "Marke löschen:",
+    jump_prompt: "Zu Marke springen:",
+    no_command: "Kein Befehl",
+    copied: "Kopiert",
+    no_link: "Kein Link gewählt, Shift-Tab wählt einen",
+    no_browser: "Konnte nicht öffnen",
+    no_jumps: "Noch keine Sprünge",
+    bookmarked: "Lesezeichen gesetzt",
+    bookmarks_keys: "Enter springt, a setzt eins hier, d löscht",
+    annotated: "Angemerkt",
+    annotations_keys: "Enter springt, a fügt eine hinzu, d löscht, ✗ nicht im Buch gefunden",
+    no_dictionary: "Nachschlagen nicht möglich mit",
+    words_keys: "Enter springt, a schlägt eines nach, d löscht",
+    reanchored: "Wiedergefunden",
+    mouse_on: "Maus an",
+    mouse_off: "Maus aus",
+    rating: "Bewertung",
+    rating_keys: "Links/Rechts, 0-5",
+    warning: "Warnung",
+    over_budget: "über dem --budget, Bilder und Farben ausgelassen",
+    images_skipped: "Bilder ausgelassen, über dem --budget",
+    same_book: "dasselbe Buch wie",
+    continue_there: "dort weiterlesen? [j/N]",
+    merge_there: "das dort Gelesene übernehmen? [j/N]",
+    yes: "j",
+    review: "Rezension",
+    review_keys: "Enter zum Bearbeiten",
+    matched: "Treffer",
+    in_chapter: "im Kapitel",
+    in_book: "im Buch",
+};
+
+static ES: Text = Text {
+    help: [
+        "Salir",
+        "Ayuda",
+        "Índice",
+        "Progreso y metadatos",
+        "Valorar y reseñar",
+        "Notas al pie en la página",
+        "Barra de progreso",
+        "Siguiente tema de color",
+        "Texto simple, sin negrita ni cursiva",
+        "Ratón sí o no, para seleccionar texto",
+        "Línea de órdenes, p. ej. :theme sepia, :set width=80",
+        "",
+        "Página siguiente",
+        "Página anterior",
+        "Media página adelante",
+        "Media página atrás",
+        "Línea siguiente",
+        "Línea anterior",
+        "Inicio del capítulo",
+        "Fin del capítulo",
+        "Ir a ese porcentaje del libro",
+        "Ir a la posición 1234, como en los metadatos",
+        "Repetir 12 veces, p. ej. 5] 3f",
+        "Capítulo anterior",
+        "Capítulo siguiente",
+        "Desplazar tablas y código",
+        "Texto más estrecho o más ancho",
+        "Texto centrado o a la izquierda",
+        "",
+        "Buscar hacia delante",
+        "Buscar hacia atrás",
+        "Repetir búsqueda hacia delante",
+        "Repetir búsqueda hacia atrás",
+        "Poner marca x",
+        "Ir a marca x",
+        "Marcadores, con nombre y guardados con el libro",
+        "Anotaciones, añadidas con :annotate y una nota",
+        "Palabras consultadas, con :define",
+        "Seguir un enlace por su etiqueta",
+        "Elegir un enlace, luego Tab al siguiente y Enter para seguirlo",
+        "Copiar el enlace elegido",
+        "Volver a antes del último salto, p. ej. un enlace",
+        "Adelante otra vez",
+        "Lista de saltos, para volver a cualquier lugar",
+    ],
+    chapter: "capítulo",
+    total: "total",
+    page: "página impresa",
+    location: "posición",
+    section: "sección",
+    not_found: "No encontrado",
+    regex: "regex",
+    mark_set: "Marca puesta",
+    no_mark: "Sin marca",
+    mark_deleted: "Marca borrada",
+    mark_prompt: "Poner marca, Supr para borrar una:",
+    unmark_prompt: "Borrar marca:",
+    jump_prompt: "Ir a marca:",
+    no_command: "Orden desconocida",
+    copied: "Copiado",
+    no_link: "Ningún enlace elegido, Shift-Tab elige uno",
+    no_browser: "No se pudo abrir",
+    no_jumps: "Aún no hay saltos",
+    bookmarked: "Marcador puesto",
+    bookmarks_keys: "Enter salta, a añade uno aquí, d borra",
+    annotated: "Anotado",
+    annotations_keys: "Enter salta, a añade una, d borra, ✗ no encontrada en el libro",
+    no_dictionary: "No se pudo consultar con",
+    words_keys: "Enter salta, a consulta una, d borra",
+    reanchored: "Encontradas de nuevo",
+    mouse_on: "Ratón activado",
+    mouse_off: "Ratón desactivado",
+    rating: "valoración",
+    rating_keys: "Izquierda/Derecha, 0-5",
+    warning: "aviso",
+    over_budget: "supera el --budget, se omiten imágenes y colores",
+    images_skipped: "se omiten imágenes, supera el --budget",
+    same_book: "el mismo libro que",
+    continue_there: "¿seguir desde allí? [s/N]",
+    merge_there: "¿combinar lo leído allí? [s/N]",
+    yes: "s",
+    review: "reseña",
+    review_keys: "Enter para editar",
+    matched: "coincidencia",
+    in_chapter: "en el capítulo",
+    in_book: "en el libro",
+};
+
+// from the locale, eg LANG=de_DE.UTF-8
+pub fn detect() -> &'static Text {
+    let lang = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|s| !s.is_empty()))
+        .unwrap_or_default();
+    match lang.get(..2) {
+        Some("de") => &DE,
+        Some("es") => &ES,
+        _ => &EN,
+    }
+}
@@ -1,27 +1,77 @@
 use crossterm::{
     cursor,
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        MouseEventKind,
+    },
     queue,
-    style::{self, Color::Rgb, Colors, Print, SetColors},
+    style::{self, Color::Rgb, Colors, Print},
     terminal,
+    tty::IsTty,
 };
+use hyphenation::{Hyphenator, Language, Load, Standard};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use std::{
-    cmp::min,
+    cell::{Cell, Ref, RefCell},
+    cmp::{min, Reverse},
     collections::HashMap,
     env, fs,
     io::{self, Write},
-    iter,
-    process::exit,
+    iter, mem, panic,
+    path::{Path, PathBuf},
+    process::{exit, Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use unicode_width::UnicodeWidthChar;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+mod screen;
+use screen::Screen;
 mod view;
-use view::{Page, Toc, View};
+use view::{status, Page, Toc, View};
 
+mod css;
 mod epub;
+mod export;
+mod fold;
+mod graphics;
+mod i18n;
+mod palette;
+use palette::Palette;
+mod term;
+mod theme;
+use theme::THEMES;
 
-fn wrap(text: &str, max_cols: usize) -> Vec<(usize, usize)> {
+// kinsoku shori, chars a line can't start or end with
+const NO_START: &str = "、。，．・：；？！ー々ゝゞヽヾ）〕］｝〉》」』】〙〗〟’”ぁぃぅぇぉっゃゅょゎゕゖァィゥェォッャュョヮヵヶ!),.:;?]}";
+const NO_END: &str = "（〔［｛〈《「『【〘〖〝‘“([{";
+// blank columns between side by side columns of text
+const GAP: u16 = 4;
+// places kept in the jump list
+const JUMPS: usize = 100;
+// bytes of text per location, about a kindle's
+const LOCATION: usize = 128;
+// bytes of text kept on either side of an annotation
+const CONTEXT: usize = 40;
+// bytes of a definition kept with the word looked up
+const DEFINITION: usize = 500;
+// browsers that take over the terminal, so bk steps aside until they're done
+const TERMINAL_BROWSERS: [&str; 8] = [
+    "lynx", "w3m", "links", "links2", "elinks", "browsh", "carbonyl", "cha",
+];
+
+// han, kana and fullwidth forms, which break between any two chars
+fn cjk(c: char) -> bool {
+    matches!(c,
+        '\u{2e80}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ffef}'
+        | '\u{20000}'..='\u{2ffff}')
+}
+
+fn wrap(text: &str, max_cols: usize, hyphenator: Option<&Standard>) -> Vec<(usize, usize)> {
     let mut lines = Vec::new();
     // bytes
     let mut start = 0;
@@ -32,11 +82,28 @@ fn wrap(text: &str, max_cols: usize) -> Vec<(usize, usize)> {
     let mut cols = 0;
     // are we breaking on whitespace?
     let mut space = false;
+    let mut prev = ' ';
 
     // should probably use unicode_segmentation grapheme_indices
     for (i, c) in text.char_indices() {
+        if (cjk(prev) || cjk(c))
+            && !prev.is_whitespace()
+            && !c.is_whitespace()
+            && !NO_END.contains(prev)
+            && !NO_START.contains(c)
+            && cols <= max_cols
+        {
+            after = 0;
+            end = i;
+            space = false;
+        }
+        prev = c;
         // https://github.com/unicode-rs/unicode-width/issues/6
-        let char_cols = c.width().unwrap_or(0);
+        let char_cols = match c {
+            // soft hyphen, only seen at a break
+            '\u{ad}' => 0,
+            _ => c.width().unwrap_or(0),
+        };
         cols += char_cols;
         match c {
             '\n' => {
@@ -55,9 +122,35 @@ fn wrap(text: &str, max_cols: usize) -> Vec<(usize, usize)> {
                 end = i + c.len_utf8();
                 space = false;
             }
+            // room for the hyphen it turns into
+            '\u{ad}' if cols < max_cols => {
+                after = 0;
+                end = i + c.len_utf8();
+                space = false;
+            }
             _ => after += char_cols,
         }
         if cols > max_cols {
+            // break inside the word with a hyphen, if there's room
+            let word = if cols == after {
+                start
+            } else if space {
+                end + 1
+            } else {
+                end
+            };
+            let hyphen = hyphenator
+                .filter(|_| !c.is_whitespace())
+                .and_then(|h| hyphen_break(h, text, word, max_cols - (cols - after)));
+            if let Some(b) = hyphen {
+                lines.push((start, b));
+                start = b;
+                end = b;
+                space = false;
+                cols = text[b..i + c.len_utf8()].width();
+                after = cols;
+                continue;
+            }
             // break a single long word
             if cols == after {
                 after = char_cols;
@@ -76,6 +169,40 @@ fn wrap(text: &str, max_cols: usize) -> Vec<(usize, usize)> {
     lines
 }
 
+// the last point in the word at from where it can break, leaving room for the hyphen
+fn hyphen_break(h: &Standard, text: &str, from: usize, room: usize) -> Option<usize> {
+    let len = text[from..]
+        .find(|c: char| !c.is_alphabetic())
+        .unwrap_or(text.len() - from);
+    let word = &text[from..from + len];
+    h.hyphenate(word)
+        .breaks
+        .into_iter()
+        .rev()
+        .find(|&b| word[..b].width() < room)
+        .map(|b| from + b)
+}
+
+// a dictionary for a language tag, eg en-US. english is built in, others are
+// read from dir, named as in the hyphenation crate, eg de-1996.standard.bincode
+fn hyphenator(lang: &str, dir: &Path) -> Option<Standard> {
+    let lang = lang.trim().to_ascii_lowercase();
+    let primary = lang.split(['-', '_']).next().unwrap_or_default();
+    let lang = Language::try_from_code(&lang)
+        .or_else(|| Language::try_from_code(primary))
+        .or(match primary {
+            "en" => Some(Language::EnglishUS),
+            "de" => Some(Language::German1996),
+            _ => None,
+        })?;
+    Standard::from_embedded(lang)
+        .or_else(|_| {
+            let path = dir.join(format!("{}.standard.bincode", lang.code()));
+            Standard::from_path(lang, path)
+        })
+        .ok()
+}
+
 struct SearchArgs {
     dir: Direction,
     skip: bool,
@@ -93,33 +220,156 @@ pub struct Bk<'a> {
     // position in the book
     chapter: usize,
     line: usize,
+    // columns scrolled in nowrap blocks
+    hscroll: usize,
+    // (chapter, byte), kept through rewraps
     mark: HashMap<char, (usize, usize)>,
+    // chapters wrapped at an old size, wrapped again when visited
+    stale: Vec<bool>,
+    // (chapter, byte) before each jump, gone back and forth through like
+    // vim's jump list. at is the place in it, its length at the latest
+    jumps: Vec<(usize, usize)>,
+    jump_at: usize,
     links: HashMap<String, (usize, usize)>,
+    // (depth, (chapter, byte), title) of each nav entry, in order
+    sections: Vec<(usize, (usize, usize), String)>,
     // layout
     colors: Colors,
+    palette: Palette,
+    // to keep the book's colors readable
+    background: Option<(u8, u8, u8)>,
+    terminal_bg: Option<(u8, u8, u8)>,
+    // index into THEMES
+    theme: usize,
+    // --fg and --bg, over every theme
+    fg: Option<(u8, u8, u8)>,
+    bg: Option<(u8, u8, u8)>,
     cols: u16,
+    // lines of text on screen
     rows: usize,
+    // blank rows after each line
+    leading: usize,
+    // blank rows above and below the text
+    margin: (usize, usize),
+    // a gauge on the last row
+    progress: bool,
+    // the time and time read on the last row
+    clock: bool,
+    // time read, counted while the terminal has focus
+    read: Duration,
+    since: Option<Instant>,
     max_width: u16,
+    // two columns side by side, if they fit
+    columns: bool,
+    // text at the left edge instead of centered
+    left: bool,
     // view state
     view: &'a dyn View,
     cursor: usize,
+    // lines scrolled in help and metadata
+    scroll: usize,
     dir: Direction,
+    // digits typed before a key, eg 50 in 50%
+    count: usize,
     meta: Vec<String>,
     query: String,
+    // typed after :
+    command: String,
+    // typed of a link's label
+    hint: String,
+    // typed after / in the Toc
+    filter: String,
+    // (chapter, index) of the link selected with shift-tab
+    link: Option<(usize, usize)>,
+    // where the link under the mouse goes, on the last row while it's there
+    hover: String,
+    // a link out of the book followed, to open in the browser
+    open: Option<String>,
+    // the query is a regular expression, not literal text
+    regex: bool,
+    // where the last query and mode matched in each chapter
+    hits: RefCell<(String, bool, Vec<Vec<usize>>)>,
+    // the last query and mode, compiled
+    pattern: RefCell<(String, bool, Option<Regex>)>,
+    // for the terminal's clipboard, sent with the next frame
+    copy: Cell<Option<String>>,
+    // feedback on the last row until the next key
+    msg: String,
+    book: Book,
+    skip: bool,
+    graphics: Option<graphics::Protocol>,
+    // pixels
+    cell: (usize, usize),
+    // lines per j/k step
+    scroll_lines: usize,
+    // run with the word to look up
+    dictionary: String,
+    // lines kept on screen when paging
+    overlap: usize,
+    // fixed pages, turned whole
+    paged: bool,
+    // the place in the chapter on the right edge
+    scrollbar: bool,
+    // lines per mouse wheel step
+    wheel: usize,
+    natural: bool,
+    // e-ink and serial consoles: page at a time, redraw only on change
+    eink: bool,
+    // off to leave the text in the scrollback
+    alt_screen: bool,
+    // off to select text with the mouse
+    mouse: bool,
+    text: &'static i18n::Text,
+    hyphenator: Option<Standard>,
+    // right to left book: aligned right, arrows mirrored
+    rtl: bool,
+    // notes on screen shown below the text
+    footnotes: bool,
+    // print page numbers beside the text
+    page_markers: bool,
+    // signs beside lines with marks and matches
+    gutter: bool,
+    // text without bold, italics and the like
+    plain: bool,
+    // author and title, for the window title
+    name: String,
 }
 
 impl Bk<'_> {
     fn new(epub: epub::Epub, args: Props) -> Self {
         let (cols, rows) = terminal::size().unwrap();
+        let status = args.progress || args.clock || args.paged;
+        let margin = (args.margin.0, args.margin.1 + status as usize);
+        let rows = text_rows(rows, margin, args.leading);
         let width = min(cols, args.width) as usize;
-        let meta = wrap(&epub.meta, width)
+        let meta = wrap(&epub.meta, width, None)
             .into_iter()
             .map(|(a, b)| String::from(&epub.meta[a..b]))
             .collect();
 
+        // a guess if the terminal doesn't say. a line is taller with leading
+        let (cw, ch) = term::cell_size().unwrap_or((10, 20));
+        let cell = (cw, ch * (args.leading + 1));
+        let hyphenator = args
+            .hyphenate
+            .as_deref()
+            .and_then(|dir| hyphenator(&epub.language, dir));
+        let [_, title, creator] = &epub.ids;
+        let name = [creator, title]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" – ");
+        let primary = epub.language.split(['-', '_']).next().unwrap_or_default();
+        let rtl = matches!(
+            primary.to_ascii_lowercase().as_str(),
+            "ar" | "dv" | "fa" | "he" | "iw" | "ku" | "ps" | "sd" | "ug" | "ur" | "yi"
+        );
         let mut chapters = epub.chapters;
+        let len = chapters.len();
         for c in &mut chapters {
-            c.lines = wrap(&c.text, width);
+            c.wrap(width, rows, cell, hyphenator.as_ref());
             if c.title.chars().count() > width {
                 c.title = c
                     .title
@@ -135,91 +385,549 @@ impl Bk<'_> {
             chapters,
             chapter: 0,
             line: 0,
-            mark: HashMap::new(),
+            hscroll: 0,
+            // as they were when the book was last closed
+            mark: args
+                .book
+                .marks
+                .iter()
+                .map(|(&c, p)| (c, (p.chapter, p.byte)))
+                .collect(),
+            stale: vec![false; len],
+            jumps: Vec::new(),
+            jump_at: 0,
+            sections: epub
+                .sections
+                .into_iter()
+                .filter_map(|(depth, url, title)| {
+                    // links are kept by file name
+                    let url = url.rsplit('/').next().unwrap_or_default();
+                    let &place = epub.links.get(url)?;
+                    Some((depth, place, title))
+                })
+                .collect(),
             links: epub.links,
-            colors: args.colors,
+            colors: Colors::new(style::Color::Reset, style::Color::Reset),
+            palette: args.palette,
+            background: None,
+            terminal_bg: args.terminal_bg,
+            theme: args.theme,
+            fg: args.fg,
+            bg: args.bg,
             cols,
-            rows: rows as usize,
+            rows,
+            leading: args.leading,
+            margin: args.margin,
+            progress: args.progress,
+            clock: args.clock,
+            read: Duration::ZERO,
+            since: Some(Instant::now()),
             max_width: args.width,
+            columns: args.columns,
+            left: args.left,
             view: if args.toc { &Toc } else { &Page },
             cursor: 0,
+            scroll: 0,
             dir: Direction::Next,
+            count: 0,
             meta,
             query: String::new(),
+            command: String::new(),
+            hint: String::new(),
+            filter: String::new(),
+            link: None,
+            hover: String::new(),
+            open: None,
+            regex: args.regex,
+            hits: RefCell::default(),
+            pattern: RefCell::default(),
+            copy: Cell::default(),
+            // eg over the memory budget
+            msg: epub
+                .meta
+                .lines()
+                .find(|l| l.starts_with(&format!("{}: ", args.text.warning)))
+                .unwrap_or_default()
+                .to_string(),
+            book: args.book,
+            skip: args.skip,
+            graphics: args.graphics,
+            cell,
+            scroll_lines: args.scroll_lines,
+            dictionary: args.dictionary,
+            overlap: args.overlap,
+            paged: args.paged,
+            scrollbar: args.scrollbar,
+            wheel: args.wheel,
+            natural: args.natural,
+            eink: args.eink,
+            alt_screen: args.alt_screen,
+            mouse: args.mouse,
+            text: args.text,
+            hyphenator,
+            rtl,
+            footnotes: args.footnotes,
+            page_markers: args.page_markers,
+            gutter: args.gutter,
+            plain: args.plain,
+            name,
         };
+        bk.apply_theme();
 
-        bk.jump_byte(args.chapter, args.byte);
+        // the book may have changed since the position was saved
+        let chapter = min(args.chapter, bk.chapters.len() - 1);
+        let byte = min(args.byte, bk.chapters[chapter].text.len());
+        bk.jump_byte(chapter, byte);
         bk.mark('\'');
 
         bk
     }
+    fn mode(&self) -> term::Mode {
+        term::Mode {
+            alt_screen: self.alt_screen,
+            eink: self.eink,
+            images: self.graphics.is_some(),
+        }
+    }
+    // take over the terminal, undone by leave
+    fn enter(&self) -> io::Result<()> {
+        self.mode().enter()
+    }
+    fn leave(&self) -> io::Result<()> {
+        self.mode().leave()
+    }
+    // give the terminal back while f runs
+    fn away<T>(&self, f: impl FnOnce() -> T) -> io::Result<T> {
+        self.leave()?;
+        let t = f();
+        // cleared along with the screen
+        for img in self.chapters.iter().flat_map(|c| &c.images) {
+            img.sent.set(false);
+        }
+        self.enter()?;
+        Ok(t)
+    }
+    // ctrl-z: until the shell resumes us
+    fn suspend(&self) -> io::Result<()> {
+        self.away(term::suspend)
+    }
+    // a link out of the book, in $BROWSER or the system's opener. waits for a
+    // browser that runs in this terminal, others are left to run
+    fn browse(&mut self, url: &str) -> io::Result<()> {
+        let browser = env::var("BROWSER").ok().filter(|s| !s.is_empty());
+        let argv: Vec<String> = match browser.as_deref().and_then(|b| b.split(':').next()) {
+            // eg firefox, or firefox %s
+            Some(b) => {
+                let mut words: Vec<_> =
+                    b.split_whitespace().map(|w| w.replace("%s", url)).collect();
+                if !b.contains("%s") {
+                    words.push(url.to_string());
+                }
+                words
+            }
+            // not cmd's start, which would run what follows a & in the url
+            None if cfg!(windows) => ["rundll32", "url.dll,FileProtocolHandler", url]
+                .map(String::from)
+                .to_vec(),
+            None if cfg!(target_os = "macos") => vec!["open".into(), url.into()],
+            None => vec!["xdg-open".into(), url.into()],
+        };
+        let Some((program, args)) = argv.split_first() else {
+            return Ok(());
+        };
+        let name = Path::new(program).file_stem().and_then(|s| s.to_str());
+        let ok = if name.is_some_and(|name| TERMINAL_BROWSERS.contains(&name)) {
+            let status = self.away(|| Command::new(program).args(args).status())?;
+            status.is_ok_and(|s| s.success())
+        } else {
+            let child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            // reaped once it's done
+            child
+                .map(|mut child| thread::spawn(move || child.wait()))
+                .is_ok()
+        };
+        if !ok {
+            self.msg = format!("{}: {}", self.text.no_browser, epub::sanitize(url));
+        }
+        Ok(())
+    }
     fn run(&mut self) -> io::Result<()> {
+        self.enter()?;
+        // the terminal goes back on errors too, and panics
+        let _guard = term::Guard(self.mode());
+        self.events()?;
+        self.leave()
+    }
+    fn events(&mut self) -> io::Result<()> {
         let mut stdout = io::stdout();
-        queue!(
-            stdout,
-            terminal::EnterAlternateScreen,
-            cursor::Hide,
-            EnableMouseCapture,
-        )?;
-        terminal::enable_raw_mode()?;
+        // everything drawn again, eg after a suspend
+        let fresh = Cell::new(false);
 
+        let mut last = (Vec::new(), String::new());
+        let mut title = String::new();
+        let mut screen = Screen::default();
+        let mut size = (0, 0);
+        let mut mouse = false;
+        let mut clear = String::new();
         let mut render = |bk: &Bk| {
+            if fresh.take() {
+                last = Default::default();
+                screen.invalidate();
+                mouse = false;
+                title.clear();
+            }
+            if bk.mouse != mouse {
+                mouse = bk.mouse;
+                if mouse {
+                    queue!(stdout, EnableMouseCapture).unwrap();
+                } else {
+                    queue!(stdout, DisableMouseCapture).unwrap();
+                }
+            }
+            if !bk.eink && bk.title() != title {
+                title = bk.title();
+                queue!(stdout, terminal::SetTitle(&title)).unwrap();
+            }
+            if let Some(text) = bk.copy.take() {
+                queue!(stdout, Print(term::osc52(&text))).unwrap();
+            }
+            let frame = (bk.view.render(bk), bk.msg.clone());
+            // every redraw is a visible refresh
+            if bk.eink && frame == last {
+                return;
+            }
+            // the terminal may have moved things around
+            let now = terminal::size().unwrap_or(size);
+            if now != size {
+                size = now;
+                screen.invalidate();
+            }
+            let mut rows = vec![String::new(); size.1 as usize];
+            let mut put = |row: usize, s: &str| {
+                if let Some(r) = rows.get_mut(row) {
+                    *r = format!("\x1b[{}G{}", bk.pad() + 1, s);
+                }
+            };
+            let row = |i: usize| bk.margin.0 + i * (bk.leading + 1);
+            for (i, line) in frame.0.iter().enumerate() {
+                put(row(i), line);
+            }
+            let bottom = (size.1 as usize).saturating_sub(1);
+            if bk.progress || bk.clock || bk.paged {
+                put(bottom, &status(bk));
+            }
+            if !bk.msg.is_empty() {
+                // long ones, eg a warning, kept to the row
+                let room = size.0.saturating_sub(bk.pad()) as usize;
+                put(bottom, &view::crop(&bk.msg, 0, room));
+            }
+            let erase = format!(
+                "{}{}{}",
+                style::Attribute::Reset,
+                bk.palette
+                    .fg(bk.colors.foreground.unwrap_or(style::Color::Reset)),
+                bk.palette
+                    .bg(bk.colors.background.unwrap_or(style::Color::Reset)),
+            );
+            // a new theme, the blank rows too
+            if erase != clear {
+                clear = erase;
+                screen.invalidate();
+            }
+            // the text can scroll, not the status row
+            let text = bk.margin.0..row(bk.rows).min(size.1 as usize);
             queue!(
                 stdout,
-                Print(style::Attribute::Reset),
-                SetColors(bk.colors),
-                terminal::Clear(terminal::ClearType::All),
+                Print(screen.draw(rows, &clear, text)),
+                cursor::MoveTo(bk.pad(), row(bk.cursor) as u16)
             )
             .unwrap();
-            for (i, line) in bk.view.render(bk).iter().enumerate() {
-                queue!(stdout, cursor::MoveTo(bk.pad(), i as u16), Print(line)).unwrap();
-            }
-            queue!(stdout, cursor::MoveTo(bk.pad(), bk.cursor as u16)).unwrap();
             stdout.flush().unwrap();
+            last = frame;
         };
 
+        self.snap();
         render(self);
+        // wheel lines not yet scrolled. touchpads send bursts of events,
+        // so spread them over a few frames, easing out
+        let mut momentum: isize = 0;
+        // read ahead while collecting typed text
+        let mut pending = None;
         loop {
-            match event::read()? {
-                Event::Key(e) => self.view.on_key(self, e.code),
-                Event::Mouse(e) => {
-                    // XXX idk seems lame
-                    if e.kind == event::MouseEventKind::Moved {
+            if momentum != 0 && pending.is_none() && !event::poll(Duration::from_millis(16))? {
+                let step = if self.eink || self.paged {
+                    momentum
+                } else {
+                    (momentum + momentum.signum()) / 2
+                };
+                momentum -= step;
+                let chapter = self.chapter;
+                self.view.on_scroll(self, step);
+                // don't fling through chapters
+                if self.chapter != chapter {
+                    momentum = 0;
+                }
+                self.snap();
+                render(self);
+                continue;
+            }
+            let event = match pending.take() {
+                Some(event) => event,
+                None if self.clock => {
+                    // wake on the minute to update the clock
+                    let secs = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs());
+                    if !event::poll(Duration::from_secs(60 - secs % 60))? {
+                        render(self);
                         continue;
                     }
-                    self.view.on_mouse(self, e);
+                    event::read()?
+                }
+                None => event::read()?,
+            };
+            match event {
+                // windows also reports releases
+                Event::Key(e) if e.kind == KeyEventKind::Release => continue,
+                Event::Key(e) => {
+                    self.msg.clear();
+                    match e.code {
+                        KeyCode::Char('z')
+                            if cfg!(unix) && e.modifiers == KeyModifiers::CONTROL =>
+                        {
+                            self.suspend()?;
+                            fresh.set(true);
+                        }
+                        KeyCode::Char(c) if e.modifiers == KeyModifiers::CONTROL => {
+                            self.view.on_ctrl(self, c)
+                        }
+                        // keys are commands while reading, held ones too
+                        KeyCode::Char(c)
+                            if (e.modifiers - KeyModifiers::SHIFT).is_empty()
+                                && !self.view.reading() =>
+                        {
+                            // IME input and pastes without bracketed paste
+                            // arrive as a burst of keys, handle them as one
+                            let mut text = String::from(c);
+                            while event::poll(Duration::ZERO)? {
+                                match event::read()? {
+                                    Event::Key(e) if e.kind == KeyEventKind::Release => (),
+                                    Event::Key(e)
+                                        if (e.modifiers - KeyModifiers::SHIFT).is_empty()
+                                            && matches!(e.code, KeyCode::Char(_)) =>
+                                    {
+                                        if let KeyCode::Char(c) = e.code {
+                                            text.push(c);
+                                        }
+                                    }
+                                    event => {
+                                        pending = Some(event);
+                                        break;
+                                    }
+                                }
+                            }
+                            self.view.on_paste(self, &text);
+                        }
+                        code => self.view.on_key(self, code),
+                    }
+                }
+                Event::Paste(text) => {
+                    let text: String = text
+                        .chars()
+                        .map(|c| if c.is_control() { ' ' } else { c })
+                        .collect();
+                    self.view.on_paste(self, &text);
+                }
+                Event::Mouse(mut e) => {
+                    let top = self.margin.0 as u16;
+                    if e.row < top && matches!(e.kind, MouseEventKind::Down(_)) {
+                        continue;
+                    }
+                    e.row = e.row.saturating_sub(top) / (self.leading as u16 + 1);
+                    if self.natural {
+                        e.kind = match e.kind {
+                            MouseEventKind::ScrollDown => MouseEventKind::ScrollUp,
+                            MouseEventKind::ScrollUp => MouseEventKind::ScrollDown,
+                            MouseEventKind::ScrollLeft => MouseEventKind::ScrollRight,
+                            MouseEventKind::ScrollRight => MouseEventKind::ScrollLeft,
+                            kind => kind,
+                        };
+                    }
+                    let wheel = self.step(self.wheel) as isize;
+                    match e.kind {
+                        MouseEventKind::ScrollDown => momentum += wheel,
+                        MouseEventKind::ScrollUp => momentum -= wheel,
+                        _ => self.view.on_mouse(self, e),
+                    }
+                    if self.eink || self.paged {
+                        // one page per burst
+                        momentum = momentum.clamp(-wheel, wheel);
+                    }
+                    if momentum != 0 {
+                        continue;
+                    }
+                }
+                // away from the book, eg in another window
+                Event::FocusLost => {
+                    self.read = self.time_read();
+                    self.since = None;
+                    momentum = 0;
+                }
+                Event::FocusGained => {
+                    self.since.get_or_insert_with(Instant::now);
                 }
                 Event::Resize(cols, rows) => {
-                    self.rows = rows as usize;
-                    if cols != self.cols {
+                    let rows = text_rows(rows, self.margins(), self.leading);
+                    // image sizes depend on the rows
+                    let cell =
+                        term::cell_size().map_or(self.cell, |(w, h)| (w, h * (self.leading + 1)));
+                    let images =
+                        self.graphics.is_some() && (rows != self.rows || cell != self.cell);
+                    self.rows = rows;
+                    self.cell = cell;
+                    if cols != self.cols || images {
                         self.cols = cols;
-                        let width = min(cols, self.max_width) as usize;
-                        for c in &mut self.chapters {
-                            c.lines = wrap(&c.text, width);
-                        }
+                        self.rewrap();
                     }
                     self.view.on_resize(self);
-                    // XXX marks aren't updated
                 }
             }
+            if let Some(url) = self.open.take() {
+                self.browse(&url)?;
+                fresh.set(true);
+            }
             if self.quit {
                 break;
             }
+            self.snap();
             render(self);
         }
-
-        queue!(
-            stdout,
-            terminal::LeaveAlternateScreen,
-            cursor::Show,
-            DisableMouseCapture
-        )?;
-        terminal::disable_raw_mode()
+        Ok(())
     }
-    fn jump(&mut self, (c, l): (usize, usize)) {
-        self.mark('\'');
-        self.chapter = c;
-        self.line = l;
+    fn jump(&mut self, (c, byte): (usize, usize)) {
+        self.save_jump();
+        self.jump_byte(c, byte);
+    }
+    // wrap again at the current size: this chapter now, as a long book takes
+    // a while, the rest when they're visited
+    fn rewrap(&mut self) {
+        self.stale.fill(true);
+        self.wrap();
+    }
+    // wrap the current chapter if it's stale, keeping the place
+    fn wrap(&mut self) {
+        let c = self.chapter;
+        if !std::mem::take(&mut self.stale[c]) {
+            return;
+        }
+        let lines = &self.chapters[c].lines;
+        let byte = lines[min(self.line, lines.len() - 1)].0;
+        let width = self.width();
+        self.chapters[c].wrap(width, self.rows, self.cell, self.hyphenator.as_ref());
+        self.jump_byte(c, byte);
+    }
+    // to n% of the book's lines at this size
+    fn percent(&mut self, n: usize) {
+        self.save_jump();
+        // every chapter's lines are needed to count them
+        let width = self.width();
+        for (c, stale) in self.chapters.iter_mut().zip(&mut self.stale) {
+            if std::mem::take(stale) {
+                c.wrap(width, self.rows, self.cell, self.hyphenator.as_ref());
+            }
+        }
+        let total: usize = self.chapters.iter().map(|c| c.lines.len()).sum();
+        let mut line = min(total * n / 100, total - 1);
+        for (c, chapter) in self.chapters.iter().enumerate() {
+            if line < chapter.lines.len() {
+                self.chapter = c;
+                self.line = line;
+                return;
+            }
+            line -= chapter.lines.len();
+        }
+    }
+    // (location, locations) of the top line: its place by bytes, the same on
+    // any screen, to share
+    fn location(&self) -> (usize, usize) {
+        let byte = self.chapters[self.chapter].lines[self.line].0;
+        let before: usize = self.chapters[..self.chapter]
+            .iter()
+            .map(|c| c.text.len())
+            .sum();
+        let total: usize = self.chapters.iter().map(|c| c.text.len()).sum();
+        ((before + byte) / LOCATION + 1, total.div_ceil(LOCATION))
+    }
+    fn go_location(&mut self, n: usize) {
+        self.save_jump();
+        let mut byte = (n - 1).saturating_mul(LOCATION);
+        for (c, chapter) in self.chapters.iter().enumerate() {
+            if byte < chapter.text.len() || c == self.chapters.len() - 1 {
+                let byte = min(byte, chapter.text.len().saturating_sub(1));
+                // wrapped at this size first
+                self.chapter = c;
+                self.wrap();
+                self.jump_byte(c, byte);
+                return;
+            }
+            byte -= chapter.text.len();
+        }
+    }
+    fn not_found(&mut self) {
+        self.msg = format!("{}: {}", self.text.not_found, self.query);
+    }
+    // eg Author – Title – Chapter 7
+    fn title(&self) -> String {
+        let chapter = &self.chapters[self.chapter].title;
+        [&self.name, chapter]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" – ")
+    }
+    fn time_read(&self) -> Duration {
+        self.read + self.since.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+    // rows kept clear of text, the status row's included
+    fn margins(&self) -> (usize, usize) {
+        let status = self.progress || self.clock || self.paged;
+        (self.margin.0, self.margin.1 + status as usize)
+    }
+    fn toggle_progress(&mut self) {
+        self.progress = !self.progress;
+        let (_, rows) = terminal::size().unwrap();
+        self.rows = text_rows(rows, self.margins(), self.leading);
+        self.rewrap();
+    }
+    // on to the next theme, remembered for this book
+    fn next_theme(&mut self) {
+        self.set_theme((self.theme + 1) % THEMES.len());
+    }
+    fn set_theme(&mut self, i: usize) {
+        self.theme = i;
+        self.apply_theme();
+        self.book.theme = THEMES[i].name.to_string();
+        self.msg = THEMES[i].name.to_string();
+    }
+    // the theme's colors, under --fg and --bg
+    fn apply_theme(&mut self) {
+        let theme = &THEMES[self.theme];
+        let (fg, bg) = (self.fg.or(theme.fg), self.bg.or(theme.bg));
+        let color = |rgb: Option<(u8, u8, u8)>| {
+            rgb.map_or(style::Color::Reset, |(r, g, b)| Rgb { r, g, b })
+        };
+        self.colors = Colors::new(color(fg), color(bg));
+        self.background = bg.or(self.terminal_bg);
+    }
+    fn set_width(&mut self, width: u16) {
+        self.max_width = width.clamp(20, self.cols.max(20));
+        self.rewrap();
     }
     fn jump_byte(&mut self, c: usize, byte: usize) {
         self.chapter = c;
@@ -231,27 +939,345 @@ impl Bk<'_> {
             Err(n) => n - 1,
         }
     }
+    fn follow(&mut self, url: &str) {
+        let Some(&(c, byte)) = self.links.get(url) else {
+            // opened once the key is handled
+            if url.starts_with("http://") || url.starts_with("https://") {
+                self.open = Some(url.to_string());
+            }
+            return;
+        };
+        self.save_jump();
+        self.jump_byte(c, byte);
+    }
+    // where a link goes: the chapter and anchor in the book, else the url
+    fn target(&self, url: &str) -> String {
+        let Some(&(c, _)) = self.links.get(url) else {
+            return format!("→ {}", epub::sanitize(url));
+        };
+        let title = &self.chapters[c].title;
+        match url.split_once('#') {
+            Some((_, anchor)) if !anchor.is_empty() => {
+                format!("→ {} #{}", title, epub::sanitize(anchor))
+            }
+            _ => format!("→ {}", title),
+        }
+    }
+    // before a jump: the place to come back to, by ' and the jump list
+    fn save_jump(&mut self) {
+        self.mark('\'');
+        let here = self.here();
+        // a new jump drops those gone back over
+        self.jumps.truncate(self.jump_at);
+        if self.jumps.last() != Some(&here) {
+            self.jumps.push(here);
+        }
+        if self.jumps.len() > JUMPS {
+            self.jumps.remove(0);
+        }
+        self.jump_at = self.jumps.len();
+    }
+    // to the place before the last jump, eg back from a link
+    fn back(&mut self) {
+        self.keep_latest();
+        if self.jump_at > 0 {
+            self.go_jump(self.jump_at - 1);
+        }
+    }
+    // undo a back
+    fn forward(&mut self) {
+        if self.jump_at + 1 < self.jumps.len() {
+            self.go_jump(self.jump_at + 1);
+        }
+    }
+    // the latest place in the jump list, to come forward to again
+    fn keep_latest(&mut self) {
+        if self.jump_at == self.jumps.len() {
+            let here = self.here();
+            if self.jumps.last() != Some(&here) {
+                self.jumps.push(here);
+            }
+            self.jump_at = self.jumps.len() - 1;
+        }
+    }
+    // to the ith place in the jump list
+    fn go_jump(&mut self, i: usize) {
+        self.keep_latest();
+        self.jump_at = i;
+        let (c, byte) = self.jumps[i];
+        self.mark('\'');
+        self.jump_byte(c, byte);
+    }
     fn jump_reset(&mut self) {
-        let &(c, l) = self.mark.get(&'\'').unwrap();
-        self.chapter = c;
-        self.line = l;
+        let &(c, byte) = self.mark.get(&'\'').unwrap();
+        self.jump_byte(c, byte);
+    }
+    // jump past repeated front matter if the top line is inside it
+    fn skip_repeated(&mut self) {
+        if !self.skip {
+            return;
+        }
+        loop {
+            let c = &self.chapters[self.chapter];
+            let byte = c.lines[self.line].0;
+            let end = match c.repeated.iter().find(|&&(a, b)| a <= byte && byte < b) {
+                Some(&(_, end)) => end,
+                None => return,
+            };
+            if end < c.lines.last().unwrap().1 {
+                self.jump_byte(self.chapter, end);
+            } else if self.chapter < self.chapters.len() - 1 {
+                self.chapter += 1;
+                self.line = 0;
+            } else {
+                return;
+            }
+        }
+    }
+    fn bookmark(&mut self, name: &str) {
+        let (chapter, byte) = self.here();
+        let place = Place::new(&self.chapters, chapter, byte);
+        self.book.bookmarks.push((name.to_string(), place));
+        self.msg = format!("{}: {}", self.text.bookmarked, name);
+    }
+    // the search match on screen, else the first line with words, with a note
+    // the first search match on screen
+    fn found(&self) -> Option<(usize, usize)> {
+        let c = &self.chapters[self.chapter];
+        let lines = &c.lines[self.line..min(self.line + self.screen(), c.lines.len())];
+        let end = lines.last().map_or(0, |l| l.1);
+        self.pattern()
+            .and_then(|re| re.find_at(&c.text[..end], lines[0].0))
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.start(), m.end()))
+    }
+    fn annotate(&mut self, note: &str) {
+        let found = self.found();
+        let c = &self.chapters[self.chapter];
+        let lines = &c.lines[self.line..min(self.line + self.screen(), c.lines.len())];
+        let line = lines
+            .iter()
+            .find(|&&(start, end)| !c.text[start..end].trim().is_empty())
+            .map(|&(start, end)| {
+                // without the line break
+                (start, start + c.text[start..end].trim_end().len())
+            });
+        let Some((start, end)) = found.or(line) else {
+            return;
+        };
+        let annotation = Annotation::new(&self.chapters, self.chapter, start, end, note);
+        let quote: Vec<_> = annotation.quote.split_whitespace().collect();
+        self.msg = format!("{}: {}", self.text.annotated, quote.join(" "));
+        self.book.annotations.push(annotation);
+    }
+    // look word up with --dictionary, or the search match on screen, keeping
+    // it in the book's word list. false if nothing was found
+    fn define(&mut self, word: &str) -> bool {
+        let (word, (chapter, byte)) = match word.trim() {
+            "" => match self.found() {
+                Some((start, end)) => {
+                    let text = &self.chapters[self.chapter].text;
+                    (text[start..end].to_string(), (self.chapter, start))
+                }
+                None => return false,
+            },
+            word => (word.to_string(), self.here()),
+        };
+        let mut argv = self.dictionary.split_whitespace();
+        let Some(program) = argv.next() else {
+            return false;
+        };
+        let output = Command::new(program)
+            .args(argv)
+            .arg(&word)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        let Ok(output) = output else {
+            self.msg = format!("{}: {}", self.text.no_dictionary, program);
+            return false;
+        };
+        let definition = String::from_utf8_lossy(&output.stdout);
+        let definition: Vec<_> = definition.split_whitespace().collect();
+        if !output.status.success() || definition.is_empty() {
+            self.msg = format!("{}: {}", self.text.not_found, word);
+            return false;
+        }
+        let lookup = Lookup::new(
+            word,
+            &definition.join(" "),
+            Place::new(&self.chapters, chapter, byte),
+        );
+        let lookups = &mut self.book.lookups;
+        lookups.retain(|l| l.word.to_lowercase() != lookup.word.to_lowercase());
+        lookups.push(lookup);
+        true
+    }
+    // find every annotation again, saying how many were
+    fn reanchor(&mut self) {
+        let total = self.book.annotations.len();
+        let found = self
+            .book
+            .annotations
+            .iter_mut()
+            .map(|a| a.refind(&self.chapters))
+            .filter(|&found| found)
+            .count();
+        self.msg = format!("{}: {}/{}", self.text.reanchored, found, total);
     }
     fn mark(&mut self, c: char) {
-        self.mark.insert(c, (self.chapter, self.line));
+        self.mark.insert(c, self.here());
+    }
+    // (chapter, byte) of the top line
+    fn here(&self) -> (usize, usize) {
+        (self.chapter, self.chapters[self.chapter].lines[self.line].0)
+    }
+    // the titles of the sections around the top line, outermost first
+    fn breadcrumb(&self) -> Vec<&str> {
+        let here = self.here();
+        let mut crumbs: Vec<(usize, &str)> = Vec::new();
+        for (depth, place, title) in &self.sections {
+            if *place > here {
+                continue;
+            }
+            crumbs.retain(|c| c.0 < *depth);
+            crumbs.push((*depth, title));
+        }
+        crumbs.into_iter().map(|c| c.1).collect()
+    }
+    // lines for a small scroll, a whole page on e-ink and fixed pages
+    fn step(&self, lines: usize) -> usize {
+        if self.eink || self.paged {
+            self.turn(self.screen())
+        } else {
+            lines
+        }
+    }
+    // lines to move for a page of this many, less the overlap
+    fn turn(&self, page: usize) -> usize {
+        if self.paged {
+            return self.screen();
+        }
+        page.saturating_sub(self.overlap).max(1)
+    }
+    // settle before drawing: the chapter wrapped, on the start of the fixed
+    // page, what's on screen counted as read
+    fn snap(&mut self) {
+        self.wrap();
+        if self.hscroll > 0 {
+            self.hscroll = min(self.hscroll, self.max_hscroll());
+        }
+        if self.paged {
+            self.line -= self.line % self.screen();
+        }
+        if self.view.reading() {
+            let lines = &self.chapters[self.chapter].lines;
+            let end = lines[min(self.line + self.screen(), lines.len()) - 1].1;
+            if self.book.seen.get(&self.chapter).map_or(0, |p| p.byte) < end {
+                let place = Place::new(&self.chapters, self.chapter, end);
+                self.book.seen.insert(self.chapter, place);
+            }
+        }
+    }
+    fn width(&self) -> usize {
+        min(self.cols, self.max_width) as usize
+    }
+    // columns to scroll right until the widest unwrapped line ends on screen
+    fn max_hscroll(&self) -> usize {
+        let c = &self.chapters[self.chapter];
+        let widest = c
+            .lines
+            .iter()
+            .filter(|&&(a, _)| c.is_nowrap(a))
+            .map(|&(a, b)| c.text[a..b].width() + c.indent(a, self.width()).width())
+            .max();
+        widest.map_or(0, |w| w.saturating_sub(self.width()))
     }
     fn pad(&self) -> u16 {
-        self.cols.saturating_sub(self.max_width) / 2
+        if self.left {
+            return 0;
+        }
+        let n = self.columns() as u16;
+        let total = self.max_width * n + GAP * (n - 1);
+        self.cols.saturating_sub(total) / 2
+    }
+    fn columns(&self) -> usize {
+        if self.columns && self.cols >= 2 * self.max_width + GAP {
+            2
+        } else {
+            1
+        }
+    }
+    // lines on one screen, down each column
+    fn screen(&self) -> usize {
+        self.rows * self.columns()
+    }
+    // where column k starts. right to left books start on the right
+    fn column_x(&self, k: usize) -> u16 {
+        let k = if self.rtl { self.columns() - 1 - k } else { k };
+        self.pad() + k as u16 * (self.width() as u16 + GAP)
+    }
+    // the query to match, in any spelling unless it's a regex. None if it's empty
+    // or doesn't compile
+    // compiled once per query and mode
+    fn pattern(&self) -> Option<Regex> {
+        let mut cache = self.pattern.borrow_mut();
+        if cache.0 != self.query || cache.1 != self.regex {
+            *cache = (self.query.clone(), self.regex, self.compile());
+        }
+        cache.2.clone()
+    }
+    fn compile(&self) -> Option<Regex> {
+        if self.query.is_empty() {
+            return None;
+        }
+        let pattern = match self.regex {
+            true => self.query.clone(),
+            false => fold::pattern(&self.query),
+        };
+        // smart case: exact only if the query has capitals
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.query.chars().any(char::is_uppercase))
+            .build()
+            .ok()
+    }
+    // the start of every match in each chapter, found once per query
+    fn hits(&self) -> Ref<'_, Vec<Vec<usize>>> {
+        let stale = {
+            let hits = self.hits.borrow();
+            hits.0 != self.query || hits.1 != self.regex
+        };
+        if stale {
+            let hits = match self.pattern() {
+                Some(re) => self
+                    .chapters
+                    .iter()
+                    .map(|c| {
+                        re.find_iter(&c.text)
+                            .filter(|m| !m.is_empty())
+                            .map(|m| m.start())
+                            .collect()
+                    })
+                    .collect(),
+                None => vec![Vec::new(); self.chapters.len()],
+            };
+            *self.hits.borrow_mut() = (self.query.clone(), self.regex, hits);
+        }
+        Ref::map(self.hits.borrow(), |h| &h.2)
     }
     fn search(&mut self, args: SearchArgs) -> bool {
+        let Some(re) = self.pattern() else {
+            return false;
+        };
         let (start, end) = self.chapters[self.chapter].lines[self.line];
         match args.dir {
             Direction::Next => {
                 let byte = if args.skip { end } else { start };
                 let head = (self.chapter, byte);
-                let tail = (self.chapter + 1..self.chapters.len() - 1).map(|n| (n, 0));
+                let tail = (self.chapter + 1..self.chapters.len()).map(|n| (n, 0));
                 for (c, byte) in iter::once(head).chain(tail) {
-                    if let Some(index) = self.chapters[c].text[byte..].find(&self.query) {
-                        self.jump_byte(c, index + byte);
+                    if let Some(m) = re.find_at(&self.chapters[c].text, byte) {
+                        self.jump_byte(c, m.start());
                         return true;
                     }
                 }
@@ -264,8 +1290,8 @@ impl Bk<'_> {
                     .rev()
                     .map(|c| (c, self.chapters[c].text.len()));
                 for (c, byte) in iter::once(head).chain(tail) {
-                    if let Some(index) = self.chapters[c].text[..byte].rfind(&self.query) {
-                        self.jump_byte(c, index);
+                    if let Some(m) = re.find_iter(&self.chapters[c].text[..byte]).last() {
+                        self.jump_byte(c, m.start());
                         return true;
                     }
                 }
@@ -285,50 +1311,583 @@ struct Args {
     #[argh(option)]
     bg: Option<String>,
 
+    /// how bold text shows, as for --italic
+    #[argh(option)]
+    bold: Option<String>,
+
+    /// show the time and the time spent reading on the last row
+    #[argh(switch)]
+    clock: bool,
+
+    /// two columns side by side when the terminal is wide enough
+    #[argh(switch)]
+    columns: bool,
+
+    /// rejoin words split across lines in converted books
+    #[argh(switch)]
+    dehyphenate: bool,
+
+    /// command to look words up with, given the word (eg sdcv -n)
+    #[argh(option, default = "String::from(\"dict\")")]
+    dictionary: String,
+
+    /// keep the save file in this directory
+    #[argh(option)]
+    dir: Option<String>,
+
+    /// memory in MB a book may use, images are skipped first
+    #[argh(option, default = "1024")]
+    budget: u64,
+
+    /// page at a time without colors, for e-ink and serial consoles
+    #[argh(switch)]
+    eink: bool,
+
+    /// print the ratings and reviews as goodreads csv or json, or the words
+    /// looked up as tsv for anki, and exit
+    #[argh(option)]
+    export: Option<String>,
+
     /// foreground color (eg f8f8f2)
     #[argh(option)]
     fg: Option<String>,
 
+    /// mark lines with marks and search matches in the margin
+    #[argh(switch)]
+    gutter: bool,
+
+    /// show footnotes at the bottom of the page
+    #[argh(switch)]
+    footnotes: bool,
+
+    /// hide furigana readings after ruby text
+    #[argh(switch)]
+    hide_ruby: bool,
+
+    /// break long words at syllables, in the book's language
+    #[argh(switch)]
+    hyphenate: bool,
+
+    /// image protocol: kitty, sixel or none. detected by default
+    #[argh(option)]
+    images: Option<String>,
+
+    /// indent paragraphs instead of separating them with blank lines
+    #[argh(switch)]
+    indent: bool,
+
+    /// italicize blockquotes
+    #[argh(switch)]
+    italic_quotes: bool,
+
+    /// how italics show: italic, bold, dim, underline, none, or a marker
+    /// around the text like _
+    #[argh(option)]
+    italic: Option<String>,
+
+    /// keep the text at the left edge instead of centered
+    #[argh(switch)]
+    left: bool,
+
+    /// blank rows below the text
+    #[argh(option, default = "0")]
+    margin_bottom: usize,
+
+    /// blank rows above the text
+    #[argh(option, default = "0")]
+    margin_top: usize,
+
     /// print metadata and exit
     #[argh(switch, short = 'm')]
     meta: bool,
 
+    /// invert the mouse wheel
+    #[argh(switch)]
+    natural: bool,
+
+    /// draw in the normal screen, leaving the text in the scrollback
+    #[argh(switch)]
+    no_alt_screen: bool,
+
+    /// leave the mouse to the terminal, to select text
+    #[argh(switch)]
+    no_mouse: bool,
+
+    /// search with regular expressions, or Tab at the prompt
+    #[argh(switch)]
+    regex: bool,
+
+    /// ignore text colors set by the book
+    #[argh(switch)]
+    no_color: bool,
+
+    /// lines of the last page kept on screen when paging
+    #[argh(option, default = "0")]
+    overlap: usize,
+
+    /// turn fixed pages, numbered on the last row
+    #[argh(switch)]
+    paged: bool,
+
+    /// colors the terminal shows: truecolor, 256 or 16. detected by default
+    #[argh(option)]
+    palette: Option<String>,
+
+    /// no bold, italics, underlines or highlights, for screen readers
+    #[argh(switch)]
+    plain: bool,
+
+    /// print page numbers in the margin, if the book marks them
+    #[argh(switch)]
+    page_markers: bool,
+
+    /// show a progress gauge on the last row
+    #[argh(switch)]
+    progress: bool,
+
+    /// keep the save file next to the executable
+    #[argh(switch)]
+    portable: bool,
+
+    /// prefix for lines of blockquotes
+    #[argh(option, default = "String::from(\"│ \")")]
+    quote: String,
+
+    /// line for scene breaks, centered
+    #[argh(option, default = "String::from(\"* * *\")")]
+    scene_break: String,
+
+    /// show the place in the chapter on the right edge, click to jump
+    #[argh(switch)]
+    scrollbar: bool,
+
+    /// lines per j/k step
+    #[argh(option, default = "3")]
+    scroll_lines: usize,
+
+    /// skip front matter repeated across chapters
+    #[argh(switch)]
+    skip_repeated: bool,
+
+    /// columns between tab stops in preformatted text
+    #[argh(option, default = "8")]
+    tabstop: usize,
+
+    /// blank rows between lines
+    #[argh(option, default = "0")]
+    line_spacing: usize,
+
+    /// blank lines between paragraphs, 0 to 2
+    #[argh(option, default = "1")]
+    spacing: usize,
+
+    /// colors: default, light, dark, sepia, solarized-light, solarized-dark,
+    /// gruvbox or high-contrast. light or dark by the terminal's background
+    /// if it says
+    #[argh(option)]
+    theme: Option<String>,
+
+    /// how underlines show, as for --italic
+    #[argh(option)]
+    underline: Option<String>,
+
     /// start with table of contents open
     #[argh(switch, short = 't')]
     toc: bool,
 
+    /// lines per mouse wheel step
+    #[argh(option, default = "3")]
+    wheel: usize,
+
     /// characters per line
     #[argh(option, short = 'w', default = "75")]
     width: u16,
 }
 
 struct Props {
-    colors: Colors,
+    palette: Palette,
+    terminal_bg: Option<(u8, u8, u8)>,
+    theme: usize,
+    fg: Option<(u8, u8, u8)>,
+    bg: Option<(u8, u8, u8)>,
     chapter: usize,
     byte: usize,
     width: u16,
+    columns: bool,
+    left: bool,
     toc: bool,
+    book: Book,
+    skip: bool,
+    graphics: Option<graphics::Protocol>,
+    scroll_lines: usize,
+    dictionary: String,
+    overlap: usize,
+    paged: bool,
+    scrollbar: bool,
+    wheel: usize,
+    natural: bool,
+    eink: bool,
+    alt_screen: bool,
+    mouse: bool,
+    text: &'static i18n::Text,
+    leading: usize,
+    margin: (usize, usize),
+    progress: bool,
+    clock: bool,
+    // where dictionaries are, when on
+    hyphenate: Option<PathBuf>,
+    footnotes: bool,
+    page_markers: bool,
+    gutter: bool,
+    plain: bool,
+    regex: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Book {
+    // 0 is unrated
+    rating: u8,
+    review: String,
+    // picked with t, empty for --theme
+    theme: String,
+    // named places, in the order added
+    bookmarks: Vec<(String, Place)>,
+    // set with m, but '
+    marks: HashMap<char, Place>,
+    // the furthest place read in each chapter, by chapter
+    seen: HashMap<usize, Place>,
+    // text picked out with a note, in the order added
+    annotations: Vec<Annotation>,
+    // words looked up, in the order last looked up
+    lookups: Vec<Lookup>,
+}
+
+impl Book {
+    // what was kept for a copy, where this has nothing
+    fn merge(&mut self, other: Book) {
+        if self.rating == 0 {
+            self.rating = other.rating;
+        }
+        if self.review.is_empty() {
+            self.review = other.review;
+        }
+        if self.theme.is_empty() {
+            self.theme = other.theme;
+        }
+        for (name, place) in other.bookmarks {
+            if !self.bookmarks.iter().any(|b| b.0 == name) {
+                self.bookmarks.push((name, place));
+            }
+        }
+        for (c, place) in other.marks {
+            self.marks.entry(c).or_insert(place);
+        }
+        for a in other.annotations {
+            if !self
+                .annotations
+                .iter()
+                .any(|b| b.quote == a.quote && b.note == a.note)
+            {
+                self.annotations.push(a);
+            }
+        }
+        for l in other.lookups {
+            if !self.lookups.iter().any(|m| m.word == l.word) {
+                self.lookups.push(l);
+            }
+        }
+        for place in other.seen.into_values() {
+            self.read(place);
+        }
+    }
+    // read up to place, if that's further on in its chapter
+    fn read(&mut self, place: Place) {
+        match self.seen.get(&place.chapter) {
+            Some(p) if p.byte >= place.byte => (),
+            _ => {
+                self.seen.insert(place.chapter, place);
+            }
+        }
+    }
+}
+
+// a place in the book kept across sessions
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Place {
+    chapter: usize,
+    byte: usize,
+    // the text there, to find it again if the book's text changes
+    snippet: String,
+}
+
+impl Place {
+    fn new(chapters: &[epub::Chapter], chapter: usize, byte: usize) -> Self {
+        Place {
+            chapter,
+            byte,
+            snippet: snippet(&chapters[chapter].text, byte).to_string(),
+        }
+    }
+    // found again in a changed book
+    fn refind(&mut self, chapters: &[epub::Chapter]) {
+        let text = chapters.get(self.chapter).map_or("", |c| &c.text);
+        if !text
+            .get(self.byte..)
+            .is_some_and(|s| s.starts_with(&self.snippet))
+        {
+            (self.chapter, self.byte) = remap(chapters, self.chapter, self.byte, &self.snippet);
+        }
+        self.chapter = min(self.chapter, chapters.len() - 1);
+        // not found, somewhere in what's left
+        let text = &chapters[self.chapter].text;
+        self.byte = min(self.byte, text.len());
+        while !text.is_char_boundary(self.byte) {
+            self.byte -= 1;
+        }
+    }
+}
+
+// text picked out of the book, found again by its words and what's around
+// them, so it survives another edition or conversion of the book
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Annotation {
+    chapter: usize,
+    start: usize,
+    end: usize,
+    quote: String,
+    // the text around it, to tell apart the same words in other places
+    before: String,
+    after: String,
+    note: String,
+    // not found in the book as it is now, kept where it was
+    lost: bool,
+}
+
+impl Annotation {
+    fn new(
+        chapters: &[epub::Chapter],
+        chapter: usize,
+        start: usize,
+        end: usize,
+        note: &str,
+    ) -> Self {
+        let text = &chapters[chapter].text;
+        let mut from = start.saturating_sub(CONTEXT);
+        while !text.is_char_boundary(from) {
+            from += 1;
+        }
+        Annotation {
+            chapter,
+            start,
+            end,
+            quote: text[start..end].to_string(),
+            before: text[from..start].to_string(),
+            after: snippet(text, end).to_string(),
+            note: note.to_string(),
+            lost: false,
+        }
+    }
+    // found again if the text changed: the same words, else the words in any
+    // spelling or spacing, else whatever is now between the text around them.
+    // false if none of those
+    fn refind(&mut self, chapters: &[epub::Chapter]) -> bool {
+        let text = chapters.get(self.chapter).map_or("", |c| &c.text);
+        if text.get(self.start..self.end) == Some(&self.quote) {
+            self.lost = false;
+            return true;
+        }
+        let exact = |c: usize| -> Vec<(usize, usize)> {
+            let text = &chapters[c].text;
+            let found = text.match_indices(&self.quote);
+            found.map(|(i, s)| (i, i + s.len())).collect()
+        };
+        let re = RegexBuilder::new(&fold::pattern(&self.quote))
+            .case_insensitive(true)
+            .build()
+            .ok()
+            .filter(|_| !self.quote.trim().is_empty());
+        let fuzzy = |c: usize| -> Vec<(usize, usize)> {
+            let text = &chapters[c].text;
+            let found = re.iter().flat_map(|re| re.find_iter(text));
+            found.map(|m| (m.start(), m.end())).collect()
+        };
+        let between = |c: usize| -> Vec<(usize, usize)> {
+            let text = &chapters[c].text;
+            if self.before.trim().is_empty() || self.after.trim().is_empty() {
+                return Vec::new();
+            }
+            let gap = self.quote.len() * 2 + CONTEXT;
+            text.match_indices(&self.before)
+                .filter_map(|(i, s)| {
+                    let start = i + s.len();
+                    let end = start + text[start..].find(&self.after)?;
+                    (end - start <= gap && start < end).then_some((start, end))
+                })
+                .collect()
+        };
+        let ways: [&dyn Fn(usize) -> Vec<(usize, usize)>; 3] = [&exact, &fuzzy, &between];
+        let best = ways.iter().find_map(|find| {
+            let found = (0..chapters.len()).flat_map(|c| find(c).into_iter().map(move |m| (c, m)));
+            // the most alike around it, then the nearest
+            found.max_by_key(|&(c, (start, end))| {
+                let text = &chapters[c].text;
+                let before = common(text[..start].chars().rev(), self.before.chars().rev());
+                let after = common(text[end..].chars(), self.after.chars());
+                let away = (c.abs_diff(self.chapter), start.abs_diff(self.start));
+                (before + after, Reverse(away))
+            })
+        });
+        if let Some((c, (start, end))) = best {
+            *self = Annotation {
+                note: mem::take(&mut self.note),
+                ..Annotation::new(chapters, c, start, end, "")
+            };
+            return true;
+        }
+        // where it was, as near as the text allows
+        self.chapter = min(self.chapter, chapters.len() - 1);
+        let text = &chapters[self.chapter].text;
+        self.end = min(self.end, text.len());
+        while !text.is_char_boundary(self.end) {
+            self.end -= 1;
+        }
+        self.start = min(self.start, self.end);
+        while !text.is_char_boundary(self.start) {
+            self.start -= 1;
+        }
+        self.lost = true;
+        false
+    }
+}
+
+// a word looked up, with the start of its definition and where it was read
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Lookup {
+    word: String,
+    definition: String,
+    place: Place,
+}
+
+impl Lookup {
+    // on one line, cut short
+    fn new(word: String, definition: &str, place: Place) -> Self {
+        let mut end = min(definition.len(), DEFINITION);
+        while !definition.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self {
+            word: epub::sanitize(&word),
+            definition: epub::sanitize(&definition[..end]),
+            place,
+        }
+    }
+}
+
+// how many chars two texts start with in common
+fn common(a: impl Iterator<Item = char>, b: impl Iterator<Item = char>) -> usize {
+    a.zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// the start of the text at byte, enough to find it again
+fn snippet(text: &str, byte: usize) -> &str {
+    let mut end = min(byte + 40, text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[byte..end]
 }
 
 #[derive(Default, Deserialize, Serialize)]
 struct Save {
     last: String,
     files: HashMap<String, (usize, usize)>,
+    #[serde(default)]
+    books: HashMap<String, Book>,
+    // layout version and text at the saved position, to find it again
+    // after an upgrade changes the chapter text
+    #[serde(default)]
+    anchors: HashMap<String, (u32, String)>,
+    // identifier and title/author, to notice a copy of a book at another path
+    #[serde(default)]
+    ids: HashMap<String, (String, String)>,
+    // copies the reader chose to keep apart, not to be asked about again
+    #[serde(default)]
+    apart: Vec<(String, String)>,
+}
+
+impl Save {
+    // move everything saved for a book to a new path
+    fn rename(&mut self, from: &str, to: &str) {
+        if let Some(pos) = self.files.remove(from) {
+            self.files.insert(to.to_string(), pos);
+        }
+        if let Some(book) = self.books.remove(from) {
+            self.books.insert(to.to_string(), book);
+        }
+        if let Some(anchor) = self.anchors.remove(from) {
+            self.anchors.insert(to.to_string(), anchor);
+        }
+        self.ids.remove(from);
+        for path in self.apart.iter_mut().flat_map(|(a, b)| [a, b]) {
+            if path == from {
+                *path = to.to_string();
+            }
+        }
+    }
+    // fold a copy into the book at to, going on from the further place
+    fn merge(&mut self, from: &str, to: &str) {
+        if let Some(pos) = self.files.remove(from) {
+            let anchor = self.anchors.remove(from);
+            if self.files.get(to).is_none_or(|&ours| pos > ours) {
+                self.files.insert(to.to_string(), pos);
+                match anchor {
+                    Some(anchor) => self.anchors.insert(to.to_string(), anchor),
+                    None => self.anchors.remove(to),
+                };
+            }
+        }
+        if let Some(theirs) = self.books.remove(from) {
+            self.books.entry(to.to_string()).or_default().merge(theirs);
+        }
+        self.ids.remove(from);
+        self.apart.retain(|(a, b)| a != from && b != from);
+        if self.last == from {
+            self.last = to.to_string();
+        }
+    }
 }
 
 struct State {
     save: Save,
-    save_path: String,
+    save_path: PathBuf,
     path: String,
     meta: bool,
+    export: Option<String>,
+    epub: epub::Options,
     bk: Props,
 }
 
+impl State {
+    // the place and the rest saved for the path, eg after a merge
+    fn reload(&mut self) {
+        let &(chapter, byte) = self.save.files.get(&self.path).unwrap_or(&(0, 0));
+        self.bk.chapter = chapter;
+        self.bk.byte = byte;
+        self.bk.book = self.save.books.get(&self.path).cloned().unwrap_or_default();
+    }
+}
+
 fn init() -> Result<State, Box<dyn std::error::Error>> {
-    let save_path = if cfg!(windows) {
-        format!("{}\\bk", env::var("APPDATA")?)
-    } else {
-        format!("{}/.local/share/bk", env::var("HOME")?)
+    let args: Args = argh::from_env();
+    let save_path = match args.dir {
+        Some(ref dir) => Path::new(dir).join("bk.ron"),
+        // eg on a usb stick
+        None if args.portable => env::current_exe()?.with_file_name("bk.ron"),
+        None if cfg!(windows) => PathBuf::from(format!("{}\\bk", env::var("APPDATA")?)),
+        None => PathBuf::from(format!("{}/.local/share/bk", env::var("HOME")?)),
     };
     // XXX will silently create a new default save if ron errors but path arg works.
     // revisit if/when stabilizing. ez file format upgrades
@@ -336,7 +1895,6 @@ fn init() -> Result<State, Box<dyn std::error::Error>> {
         ron::from_str(&s)
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid save file"))
     });
-    let args: Args = argh::from_env();
 
     let path = match args.path {
         Some(p) => Some(fs::canonicalize(p)?.to_str().unwrap().to_string()),
@@ -344,6 +1902,8 @@ fn init() -> Result<State, Box<dyn std::error::Error>> {
     };
 
     let (path, save, chapter, byte) = match (save, path) {
+        // nothing rated yet
+        (Err(_), None) if args.export.is_some() => (String::new(), Save::default(), 0, 0),
         (Err(e), None) => return Err(Box::new(e)),
         (Err(_), Some(p)) => (p, Save::default(), 0, 0),
         (Ok(s), None) => {
@@ -360,63 +1920,371 @@ fn init() -> Result<State, Box<dyn std::error::Error>> {
         }
     };
 
-    // XXX oh god what
-    let fg = args
-        .fg
-        .map(|s| Rgb {
-            r: u8::from_str_radix(&s[0..2], 16).unwrap(),
-            g: u8::from_str_radix(&s[2..4], 16).unwrap(),
-            b: u8::from_str_radix(&s[4..6], 16).unwrap(),
-        })
-        .unwrap_or(style::Color::Reset);
-    let bg = args
-        .bg
-        .map(|s| Rgb {
-            r: u8::from_str_radix(&s[0..2], 16).unwrap(),
-            g: u8::from_str_radix(&s[2..4], 16).unwrap(),
-            b: u8::from_str_radix(&s[4..6], 16).unwrap(),
-        })
-        .unwrap_or(style::Color::Reset);
+    let text = i18n::detect();
+    let graphics = match args.images.as_deref() {
+        Some("kitty") => Some(graphics::Protocol::Kitty),
+        Some("sixel") => Some(graphics::Protocol::Sixel),
+        Some("none") => None,
+        Some(s) => return Err(format!("unknown image protocol: {}", s).into()),
+        None if args.meta || args.export.is_some() => None,
+        None => graphics::detect(),
+    };
+
+    let palette = match args.palette.as_deref() {
+        Some(s) => Palette::parse(s).ok_or(format!("unknown palette: {}", s))?,
+        None => Palette::detect(),
+    };
+
+    let book = save.books.get(&path).cloned().unwrap_or_default();
+
+    let terminal_bg = if args.meta || args.export.is_some() || args.eink || args.no_color {
+        None
+    } else {
+        term::background()
+    };
+    let hex = |arg: &Option<String>| {
+        let rgb = arg
+            .as_deref()
+            .map(|s| theme::hex(s).ok_or(format!("bad color: {}", s)));
+        rgb.transpose()
+    };
+    let (fg, bg) = (hex(&args.fg)?, hex(&args.bg)?);
+    // --theme, else the one last picked for this book, else light or dark
+    // text for the background
+    let theme = match args.theme.as_deref() {
+        Some(s) => theme::find(s).ok_or(format!("unknown theme: {}", s))?,
+        None => {
+            theme::find(&book.theme).unwrap_or_else(|| bg.or(terminal_bg).map_or(0, theme::auto))
+        }
+    };
+    // no colors at all
+    let (theme, fg, bg) = match args.eink {
+        true => (0, None, None),
+        false => (theme, fg, bg),
+    };
 
     Ok(State {
         path,
         save,
-        save_path,
         meta: args.meta,
+        export: args.export,
+        epub: epub::Options {
+            images: graphics.is_some(),
+            tabstop: args.tabstop.max(1),
+            indent: args.indent,
+            spacing: min(args.spacing, 2),
+            budget: args.budget.saturating_mul(1 << 20),
+            dehyphenate: args.dehyphenate,
+            ruby: !args.hide_ruby,
+            colors: !args.no_color && !args.eink,
+            quote: args.quote,
+            scene_break: args.scene_break,
+            italic_quotes: args.italic_quotes,
+            italic: emphasis(&args.italic, "italic"),
+            bold: emphasis(&args.bold, "bold"),
+            underline: emphasis(&args.underline, "underline"),
+            width: terminal::size().map_or(args.width, |(cols, _)| min(cols, args.width)) as usize,
+            text,
+        },
         bk: Props {
-            colors: Colors::new(fg, bg),
+            palette,
+            terminal_bg,
+            theme,
+            fg,
+            bg,
             chapter,
             byte,
             width: args.width,
+            columns: args.columns,
+            left: args.left,
             toc: args.toc,
+            book,
+            skip: args.skip_repeated,
+            graphics,
+            scroll_lines: args.scroll_lines.max(1),
+            dictionary: args.dictionary,
+            overlap: args.overlap,
+            paged: args.paged,
+            scrollbar: args.scrollbar,
+            wheel: args.wheel,
+            natural: args.natural,
+            eink: args.eink,
+            alt_screen: !args.eink && !args.no_alt_screen,
+            mouse: !args.no_mouse,
+            text,
+            leading: args.line_spacing,
+            margin: (args.margin_top, args.margin_bottom),
+            progress: args.progress,
+            clock: args.clock,
+            // bk's own, eg ~/.local/share/bk-hyphenation
+            hyphenate: args
+                .hyphenate
+                .then(|| save_path.with_file_name("bk-hyphenation")),
+            footnotes: args.footnotes,
+            page_markers: args.page_markers,
+            gutter: args.gutter,
+            plain: args.plain,
+            regex: args.regex,
         },
+        save_path,
     })
 }
 
+// --italic and the like, the attribute itself by default
+fn emphasis(arg: &Option<String>, default: &str) -> epub::Emphasis {
+    epub::Emphasis::parse(arg.as_deref().unwrap_or(default))
+}
+
+// lines of text that fit between the margins, at least one
+fn text_rows(rows: u16, margin: (usize, usize), leading: usize) -> usize {
+    let rows = (rows as usize).saturating_sub(margin.0 + margin.1);
+    ((rows + leading) / (leading + 1)).max(1)
+}
+
+// a yes or no question before the ui starts
+fn ask(question: &str, yes: &str) -> bool {
+    if !io::stdin().is_tty() {
+        return false;
+    }
+    print!("{} ", question);
+    let mut answer = String::new();
+    io::stdout().flush().is_ok()
+        && io::stdin().read_line(&mut answer).is_ok()
+        && [yes, "y"]
+            .iter()
+            .any(|y| answer.trim().eq_ignore_ascii_case(y))
+}
+
+// find saved text again, preferring the closest match in the same chapter
+fn remap(chapters: &[epub::Chapter], chapter: usize, byte: usize, snippet: &str) -> (usize, usize) {
+    if snippet.is_empty() {
+        return (chapter, byte);
+    }
+    let find = |c: usize| {
+        chapters[c]
+            .text
+            .match_indices(snippet)
+            .map(|(i, _)| i)
+            .min_by_key(|&i| i.abs_diff(byte))
+    };
+    let same = (chapter < chapters.len()).then(|| find(chapter)).flatten();
+    match same {
+        Some(i) => (chapter, i),
+        None => (0..chapters.len())
+            .find_map(|c| find(c).map(|i| (c, i)))
+            .unwrap_or((chapter, byte)),
+    }
+}
+
+// the books rated or reviewed, with their titles from the files
+// [identifier, title, author] of the book at path
+fn ids(path: &str, opts: &epub::Options) -> [String; 3] {
+    let [identifier, title, author] = epub::Epub::new(path, true, opts.clone())
+        .map(|epub| epub.ids)
+        .unwrap_or_default();
+    // a book since moved or deleted
+    let title = match title.is_empty() {
+        true => Path::new(path)
+            .file_stem()
+            .map_or(String::new(), |s| s.to_string_lossy().to_string()),
+        false => title,
+    };
+    [identifier, title, author]
+}
+
+fn export(save: &Save, format: &str, opts: epub::Options) -> Option<String> {
+    let write: fn(&[export::Row]) -> String = match format {
+        "csv" => export::csv,
+        "json" => export::json,
+        "words" => {
+            let mut books: Vec<_> = save
+                .books
+                .iter()
+                .filter(|b| !b.1.lookups.is_empty())
+                .collect();
+            books.sort_by_key(|b| b.0);
+            let words: Vec<export::Word> = books
+                .into_iter()
+                .flat_map(|(path, book)| {
+                    let [_, title, _] = ids(path, &opts);
+                    book.lookups.iter().map(move |l| export::Word {
+                        word: l.word.clone(),
+                        definition: l.definition.clone(),
+                        title: title.clone(),
+                    })
+                })
+                .collect();
+            return Some(export::tsv(&words));
+        }
+        _ => return None,
+    };
+    let mut rows: Vec<export::Row> = save
+        .books
+        .iter()
+        .filter(|(_, book)| book.rating > 0 || !book.review.is_empty())
+        .map(|(path, book)| {
+            let [identifier, title, author] = ids(path, &opts);
+            export::Row {
+                title,
+                author,
+                identifier,
+                rating: book.rating,
+                review: book.review.clone(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| (&a.title, &a.author).cmp(&(&b.title, &b.author)));
+    Some(write(&rows))
+}
+
 fn main() {
     let mut state = init().unwrap_or_else(|e| {
         println!("init error: {}", e);
         exit(1);
     });
-    let epub = epub::Epub::new(&state.path, state.meta).unwrap_or_else(|e| {
+    if let Some(format) = &state.export {
+        match export(&state.save, format, state.epub) {
+            Some(s) => print!("{}", s),
+            None => {
+                println!("unknown export format: {}", format);
+                exit(1);
+            }
+        }
+        exit(0);
+    }
+    let epub = epub::Epub::new(&state.path, state.meta, state.epub.clone()).unwrap_or_else(|e| {
         println!("epub error: {}", e);
         exit(1);
     });
     if state.meta {
-        println!("{}", epub.meta);
+        print!("{}", epub.meta);
+        let book = &state.bk.book;
+        if book.rating > 0 {
+            println!("rating: {}/5", book.rating);
+        }
+        if !book.review.is_empty() {
+            println!("review: {}", book.review);
+        }
         exit(0);
     }
+    // a new path may be a copy of a book we've read, eg downloaded again
+    let [identifier, title, creator] = &epub.ids;
+    let name: String = format!("{} {}", title, creator)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let ids = (identifier.trim().to_string(), name);
+    let same = |(i, n): &(String, String)| {
+        (!i.is_empty() && *i == ids.0) || (!n.is_empty() && *n == ids.1)
+    };
+    let apart = |path: &str| {
+        let here = state.path.as_str();
+        let pairs = state.save.apart.iter();
+        pairs
+            .map(|(a, b)| (a.as_str(), b.as_str()))
+            .any(|pair| pair == (path, here) || pair == (here, path))
+    };
+    let copies: Vec<String> = state
+        .save
+        .ids
+        .iter()
+        .filter(|&(path, other)| *path != state.path && same(other) && !apart(path))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let text = state.bk.text;
+    let question = |old: &str, then: &str| format!("{} {}, {}", text.same_book, old, then);
+    if !state.save.files.contains_key(&state.path) {
+        if let Some(old) = copies.first() {
+            if ask(&question(old, text.continue_there), text.yes) {
+                state.save.rename(old, &state.path);
+                state.reload();
+            } else {
+                state.save.apart.push((old.clone(), state.path.clone()));
+            }
+        }
+    } else {
+        // copies read before, each with its own progress
+        for old in &copies {
+            if ask(&question(old, text.merge_there), text.yes) {
+                state.save.merge(old, &state.path);
+                state.reload();
+            } else {
+                state.save.apart.push((old.clone(), state.path.clone()));
+            }
+        }
+    }
+    state.save.ids.insert(state.path.clone(), ids);
+
+    // options like --indent change the text too
+    let moved = |snippet: &str| {
+        let text = epub.chapters.get(state.bk.chapter).map_or("", |c| &c.text);
+        !text
+            .get(state.bk.byte..)
+            .is_some_and(|s| s.starts_with(snippet))
+    };
+    match state.save.anchors.get(&state.path) {
+        Some((layout, snippet)) if *layout != epub::LAYOUT || moved(snippet) => {
+            let (chapter, byte) = remap(&epub.chapters, state.bk.chapter, state.bk.byte, snippet);
+            state.bk.chapter = chapter;
+            state.bk.byte = byte;
+        }
+        _ => (),
+    }
+    let book = &mut state.bk.book;
+    let places = book.bookmarks.iter_mut().map(|b| &mut b.1);
+    for place in places.chain(book.marks.values_mut()) {
+        place.refind(&epub.chapters);
+    }
+    for annotation in &mut book.annotations {
+        annotation.refind(&epub.chapters);
+    }
+    for lookup in &mut book.lookups {
+        lookup.place.refind(&epub.chapters);
+    }
+    // by the chapters they're in now
+    for mut place in mem::take(&mut book.seen).into_values() {
+        place.refind(&epub.chapters);
+        book.read(place);
+    }
+    // a panic message on a usable terminal
+    let mode = term::Mode {
+        alt_screen: state.bk.alt_screen,
+        eink: state.bk.eink,
+        images: state.bk.graphics.is_some(),
+    };
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = mode.leave();
+        hook(info);
+    }));
     let mut bk = Bk::new(epub, state.bk);
     bk.run().unwrap_or_else(|e| {
         println!("run error: {}", e);
         exit(1);
     });
 
+    let text = &bk.chapters[bk.chapter].text;
     let byte = bk.chapters[bk.chapter].lines[bk.line].0;
+    state.save.anchors.insert(
+        state.path.clone(),
+        (epub::LAYOUT, snippet(text, byte).to_string()),
+    );
     state
         .save
         .files
         .insert(state.path.clone(), (bk.chapter, byte));
+    bk.book.marks = bk
+        .mark
+        .iter()
+        .filter(|(&c, _)| c != '\'')
+        .map(|(&c, &(chapter, byte))| (c, Place::new(&bk.chapters, chapter, byte)))
+        .collect();
+    if bk.book == Book::default() {
+        state.save.books.remove(&state.path);
+    } else {
+        state.save.books.insert(state.path.clone(), bk.book);
+    }
     state.save.last = state.path;
     let serialized = ron::to_string(&state.save).unwrap();
     fs::write(state.save_path, serialized).unwrap_or_else(|e| {
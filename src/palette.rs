@@ -0,0 +1,145 @@
+// colors the terminal can show: --fg, --bg and the book's own
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
+use std::env;
+
+// xterm's defaults for the first 16
+const ANSI: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Palette {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl Palette {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "truecolor" => Some(Palette::TrueColor),
+            "256" => Some(Palette::Ansi256),
+            "16" => Some(Palette::Ansi16),
+            _ => None,
+        }
+    }
+    // from the environment, as terminfo doesn't say about truecolor
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Palette::TrueColor
+        } else if cfg!(windows) {
+            // the console has done truecolor since windows 10
+            Palette::TrueColor
+        } else if term.contains("256") {
+            Palette::Ansi256
+        } else {
+            Palette::Ansi16
+        }
+    }
+    pub fn fg(self, color: Color) -> String {
+        match self.fit(color) {
+            Color::AnsiValue(n) if self == Palette::Ansi16 => sgr(30, n),
+            color => SetForegroundColor(color).to_string(),
+        }
+    }
+    pub fn bg(self, color: Color) -> String {
+        match self.fit(color) {
+            Color::AnsiValue(n) if self == Palette::Ansi16 => sgr(40, n),
+            color => SetBackgroundColor(color).to_string(),
+        }
+    }
+    fn fit(self, color: Color) -> Color {
+        let rgb = match (self, color) {
+            (Palette::TrueColor, _) | (_, Color::Reset) => return color,
+            (Palette::Ansi256, Color::AnsiValue(_)) => return color,
+            (Palette::Ansi16, Color::AnsiValue(n)) if n < 16 => return color,
+            (_, Color::Rgb { r, g, b }) => (r, g, b),
+            (_, Color::AnsiValue(n)) => rgb(n),
+            // named colors are already among the 16
+            _ => return color,
+        };
+        let n = match self {
+            Palette::Ansi16 => nearest(rgb, 0..16),
+            _ => nearest(rgb, 16..=255),
+        };
+        Color::AnsiValue(n)
+    }
+}
+
+// if text in this color stands out enough on the background, by the wcag
+// contrast ratio. books set dark colors for white pages
+pub fn readable(color: Color, background: (u8, u8, u8)) -> bool {
+    let rgb = match color {
+        Color::Rgb { r, g, b } => (r, g, b),
+        Color::AnsiValue(n) => rgb(n),
+        _ => return true,
+    };
+    let (a, b) = (luminance(rgb), luminance(background));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05) >= 3.0
+}
+
+// if dark text stands out more on it than light
+pub fn light(background: (u8, u8, u8)) -> bool {
+    let l = luminance(background);
+    (l + 0.05) / 0.05 > 1.05 / (l + 0.05)
+}
+
+fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    let linear = |v: u8| {
+        let v = v as f32 / 255.0;
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+// eg 31 for dark red, 91 for red
+fn sgr(base: u8, n: u8) -> String {
+    let code = if n < 8 { base + n } else { base + 60 + n - 8 };
+    format!("\x1b[{}m", code)
+}
+
+fn rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI[n as usize],
+        16..=231 => {
+            let n = n - 16;
+            let level = |v: u8| CUBE[v as usize];
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let v = 8 + (n - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+fn nearest((r, g, b): (u8, u8, u8), range: impl Iterator<Item = u8>) -> u8 {
+    let dist = |n: &u8| {
+        let (r2, g2, b2) = rgb(*n);
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    range.min_by_key(dist).unwrap()
+}
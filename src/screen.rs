@@ -0,0 +1,169 @@
+// draws frames of whole rows, only those that changed since the last
+use std::ops::Range;
+
+// images are placed by escapes and outlive the text around them, so frames
+// with images are drawn from a cleared screen
+fn graphics(row: &str) -> bool {
+    row.contains("\x1b_G") || row.contains("\x1bP")
+}
+
+#[derive(Default)]
+pub struct Screen {
+    // rows as drawn, empty before the first frame
+    last: Vec<String>,
+}
+
+impl Screen {
+    // draw everything next time, eg after a resize
+    pub fn invalidate(&mut self) {
+        self.last.clear();
+    }
+    // the escapes to draw a frame of rows over the last one. each row drawn
+    // starts with `clear`, to set the attributes and colors it's erased with.
+    // rows in `scroll` may be moved by the terminal instead of drawn again
+    pub fn draw(&mut self, frame: Vec<String>, clear: &str, scroll: Range<usize>) -> String {
+        let full = self.last.len() != frame.len()
+            || frame.iter().chain(&self.last).any(|row| graphics(row));
+        let mut s = String::new();
+        if full {
+            s.push_str(clear);
+            s.push_str("\x1b[2J");
+        } else if let Some(k) = self.shift(&frame, &scroll) {
+            // the rows scrolled in are blank
+            let (top, end) = (scroll.start, scroll.end);
+            let moved: Vec<String> = (top..end)
+                .map(|i| match i.checked_add_signed(k) {
+                    Some(j) if scroll.contains(&j) => self.last[j].clone(),
+                    _ => String::new(),
+                })
+                .collect();
+            self.last.splice(top..end, moved);
+            let dir = if k > 0 { 'S' } else { 'T' };
+            s.push_str(&format!(
+                "{}\x1b[{};{}r\x1b[{}{}\x1b[r",
+                clear,
+                top + 1,
+                end,
+                k.unsigned_abs(),
+                dir
+            ));
+        }
+        for (i, row) in frame.iter().enumerate() {
+            let same = if full {
+                row.is_empty()
+            } else {
+                self.last[i] == *row
+            };
+            if !same {
+                s.push_str(&format!("\x1b[{};1H{}\x1b[2K{}", i + 1, clear, row));
+            }
+        }
+        self.last = frame;
+        s
+    }
+    // rows to scroll the region by, up if positive, if that leaves fewer to draw
+    fn shift(&self, frame: &[String], scroll: &Range<usize>) -> Option<isize> {
+        let scroll = scroll.start..scroll.end.min(frame.len());
+        let n = scroll.len() as isize;
+        // rows that differ after scrolling by k
+        let cost = |k: isize| {
+            scroll
+                .clone()
+                .filter(|&i| {
+                    let old = match i.checked_add_signed(k) {
+                        Some(j) if scroll.contains(&j) => self.last[j].as_str(),
+                        _ => "",
+                    };
+                    frame[i] != old
+                })
+                .count()
+        };
+        let still = cost(0);
+        (1 - n..n)
+            .filter(|&k| k != 0)
+            .map(|k| (cost(k), k.abs(), k))
+            .min()
+            .filter(|&(c, _, _)| c < still)
+            .map(|(_, _, k)| k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn first_frame_clears() {
+        let mut screen = Screen::default();
+        let s = screen.draw(frame(&["a", "", "c"]), "", 0..0);
+        assert_eq!(s, "\x1b[2J\x1b[1;1H\x1b[2Ka\x1b[3;1H\x1b[2Kc");
+    }
+
+    #[test]
+    fn same_frame_draws_nothing() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b"]), "", 0..0);
+        assert_eq!(screen.draw(frame(&["a", "b"]), "", 0..0), "");
+    }
+
+    #[test]
+    fn only_changed_rows() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b", "c"]), "", 0..0);
+        let s = screen.draw(frame(&["a", "x", "c"]), "<", 0..0);
+        assert_eq!(s, "\x1b[2;1H<\x1b[2Kx");
+    }
+
+    #[test]
+    fn emptied_rows_are_erased() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b"]), "", 0..0);
+        assert_eq!(screen.draw(frame(&["a", ""]), "", 0..0), "\x1b[2;1H\x1b[2K");
+    }
+
+    #[test]
+    fn resize_and_invalidate_redraw() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b"]), "", 0..0);
+        assert!(screen.draw(frame(&["a"]), "", 0..0).starts_with("\x1b[2J"));
+        screen.invalidate();
+        assert!(screen.draw(frame(&["a"]), "", 0..0).starts_with("\x1b[2J"));
+    }
+
+    #[test]
+    fn scrolls_the_region() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b", "c", "d", "s"]), "", 0..4);
+        // down a line: up one row, draw the new last
+        let s = screen.draw(frame(&["b", "c", "d", "e", "s"]), "", 0..4);
+        assert_eq!(s, "\x1b[1;4r\x1b[1S\x1b[r\x1b[4;1H\x1b[2Ke");
+        // and back
+        let s = screen.draw(frame(&["a", "b", "c", "d", "s"]), "", 0..4);
+        assert_eq!(s, "\x1b[1;4r\x1b[1T\x1b[r\x1b[1;1H\x1b[2Ka");
+    }
+
+    #[test]
+    fn no_scroll_for_a_new_page() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["a", "b", "c"]), "", 0..3);
+        let s = screen.draw(frame(&["x", "y", "z"]), "", 0..3);
+        assert!(!s.contains('r'));
+    }
+
+    #[test]
+    fn images_redraw() {
+        let mut screen = Screen::default();
+        screen.draw(frame(&["\x1b_Ga=p\x1b\\", "b"]), "", 0..0);
+        let s = screen.draw(frame(&["\x1b_Ga=p\x1b\\", "b"]), "", 0..0);
+        assert!(s.starts_with("\x1b[2J"));
+        // and once more after the image is gone
+        assert!(screen
+            .draw(frame(&["a", "b"]), "", 0..0)
+            .starts_with("\x1b[2J"));
+        assert_eq!(screen.draw(frame(&["a", "b"]), "", 0..0), "");
+    }
+}
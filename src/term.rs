@@ -0,0 +1,261 @@
+// terminal queries that crossterm doesn't provide, and taking the terminal
+// over and giving it back
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// write an escape sequence and read the reply. primary device attributes
+// follow as a sentinel: every terminal answers them, in order, so the reply
+// is read to the end and isn't left to be taken for keys. gives up after a
+// timeout. returns what came before the device attributes, and them
+#[cfg(unix)]
+pub fn query(seq: &str) -> Option<(String, String)> {
+    use crossterm::{terminal, tty::IsTty};
+    use std::io::{self, Write};
+
+    if !io::stdin().is_tty() || !io::stdout().is_tty() {
+        return None;
+    }
+    terminal::enable_raw_mode().ok()?;
+    let mut stdout = io::stdout();
+    let _ = stdout
+        .write_all(format!("{}\x1b[c", seq).as_bytes())
+        .and_then(|_| stdout.flush());
+
+    let mut reply = Vec::new();
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // where the device attributes start, eg \x1b[?62;4c
+    let mut da = None;
+    loop {
+        // SAFETY: fd is a valid pollfd and byte is a 1 byte buffer
+        let ready = unsafe { libc::poll(&mut fd, 1, 500) };
+        if ready <= 0 {
+            break;
+        }
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut _, 1) };
+        if n != 1 {
+            break;
+        }
+        reply.push(byte);
+        if da.is_none() && reply.ends_with(b"\x1b[?") {
+            da = Some(reply.len() - 3);
+        }
+        if da.is_some() && byte == b'c' {
+            break;
+        }
+    }
+    terminal::disable_raw_mode().ok()?;
+    let da = da?;
+    let reply = String::from_utf8(reply).ok()?;
+    Some((reply[..da].to_string(), reply[da..].to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn query(_: &str) -> Option<(String, String)> {
+    None
+}
+
+// (width, height) of a cell in pixels
+#[cfg(unix)]
+pub fn cell_size() -> Option<(usize, usize)> {
+    // SAFETY: winsize is plain data, filled in by the ioctl
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } != 0
+        || ws.ws_col == 0
+        || ws.ws_row == 0
+        || ws.ws_xpixel == 0
+        || ws.ws_ypixel == 0
+    {
+        return None;
+    }
+    Some((
+        (ws.ws_xpixel / ws.ws_col) as usize,
+        (ws.ws_ypixel / ws.ws_row) as usize,
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn cell_size() -> Option<(usize, usize)> {
+    None
+}
+
+// (hours, minutes) of the local time
+#[cfg(unix)]
+pub fn local_time() -> (u32, u32) {
+    // SAFETY: tm is plain data, filled in by localtime_r
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    (tm.tm_hour as u32, tm.tm_min as u32)
+}
+
+// utc, without the time zone
+#[cfg(not(unix))]
+pub fn local_time() -> (u32, u32) {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    ((secs / 3600 % 24) as u32, (secs / 60 % 60) as u32)
+}
+
+// the background color, from xterm's OSC 11, eg \x1b]11;rgb:2828/2a2a/3636\x1b\\
+pub fn background() -> Option<(u8, u8, u8)> {
+    let (reply, _) = query("\x1b]11;?\x1b\\")?;
+    let rgb = reply.split("rgb:").nth(1)?;
+    let rgb = rgb.trim_end_matches(['\x1b', '\\', '\x07']);
+    let mut v = rgb.split('/').map(|hex| {
+        // 1 to 4 hex digits, scaled to 8 bits
+        let max = 16u32.checked_pow(hex.len() as u32)?.checked_sub(1)?;
+        let n = u32::from_str_radix(hex, 16).ok()?;
+        Some((n * 255 / max.max(1)) as u8)
+    });
+    Some((v.next()??, v.next()??, v.next()??))
+}
+
+// stop like ctrl-z would, returning once the shell continues us
+#[cfg(unix)]
+pub fn suspend() {
+    // SAFETY: raising a signal has no memory effects
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn suspend() {}
+
+// whether bk has the terminal, so it's given back once
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+// how bk has the terminal, to give it back from anywhere, eg a panic
+#[derive(Clone, Copy)]
+pub struct Mode {
+    pub alt_screen: bool,
+    pub eink: bool,
+    // kitty images to delete
+    pub images: bool,
+}
+
+impl Mode {
+    // take over the terminal, undone by leave
+    pub fn enter(self) -> std::io::Result<()> {
+        use crossterm::{cursor, event, queue, style::Print, terminal};
+        use std::io;
+
+        let mut stdout = io::stdout();
+        if self.alt_screen {
+            queue!(stdout, terminal::EnterAlternateScreen)?;
+        }
+        if !self.eink {
+            // save the window title, to put it back after
+            queue!(stdout, Print("\x1b[22;0t"))?;
+        }
+        queue!(
+            stdout,
+            cursor::Hide,
+            event::EnableBracketedPaste,
+            event::EnableFocusChange
+        )?;
+        ENTERED.store(true, Ordering::SeqCst);
+        terminal::enable_raw_mode()
+    }
+    // give the terminal back, if bk has it
+    pub fn leave(self) -> std::io::Result<()> {
+        use crate::graphics;
+        use crossterm::{cursor, event, queue, style::Attribute, style::Print, terminal};
+        use std::io::{self, Write};
+
+        if !ENTERED.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        if self.images {
+            queue!(stdout, Print(graphics::clear()))?;
+        }
+        if self.eink {
+            queue!(
+                stdout,
+                terminal::Clear(terminal::ClearType::All),
+                cursor::MoveTo(0, 0)
+            )?;
+        } else if self.alt_screen {
+            queue!(stdout, terminal::LeaveAlternateScreen)?;
+        } else {
+            // leave the page in the scrollback, the prompt below it
+            let (_, rows) = terminal::size()?;
+            queue!(
+                stdout,
+                Print(Attribute::Reset),
+                cursor::MoveTo(0, rows.saturating_sub(1)),
+                Print("\n"),
+            )?;
+        }
+        if !self.eink {
+            queue!(stdout, Print("\x1b[23;0t"))?;
+        }
+        queue!(
+            stdout,
+            Print(Attribute::Reset),
+            cursor::Show,
+            event::DisableMouseCapture,
+            event::DisableBracketedPaste,
+            event::DisableFocusChange,
+        )?;
+        stdout.flush()?;
+        terminal::disable_raw_mode()
+    }
+}
+
+// gives the terminal back however the scope is left, eg by an error
+pub struct Guard(pub Mode);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = self.0.leave();
+    }
+}
+
+// puts text on the clipboard, which the terminal may ignore. drawn with a
+// frame, so it doesn't land in the middle of one
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64::encode(text))
+}
+
+// put text on the clipboard by the system's tool, if there is one
+pub fn copy(text: &str) {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    let tools: &[(&str, &[&str])] = &[
+        ("wl-copy", &[]),
+        ("xclip", &["-selection", "clipboard"]),
+        ("xsel", &["--clipboard", "--input"]),
+        ("pbcopy", &[]),
+    ];
+    for (tool, args) in tools {
+        let child = Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            if child.wait().is_ok_and(|s| s.success()) {
+                return;
+            }
+        }
+    }
+}
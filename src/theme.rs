@@ -0,0 +1,78 @@
+// named text and background colors, for --theme
+use crate::palette;
+
+pub struct Theme {
+    pub name: &'static str,
+    // None leaves it to the terminal
+    pub fg: Option<(u8, u8, u8)>,
+    pub bg: Option<(u8, u8, u8)>,
+}
+
+pub const THEMES: [Theme; 8] = [
+    Theme {
+        name: "default",
+        fg: None,
+        bg: None,
+    },
+    // text for the terminal's own background
+    Theme {
+        name: "light",
+        fg: Some((0x24, 0x24, 0x24)),
+        bg: None,
+    },
+    Theme {
+        name: "dark",
+        fg: Some((0xdc, 0xdc, 0xdc)),
+        bg: None,
+    },
+    Theme {
+        name: "sepia",
+        fg: Some((0x5b, 0x46, 0x36)),
+        bg: Some((0xf4, 0xec, 0xd8)),
+    },
+    Theme {
+        name: "solarized-light",
+        fg: Some((0x65, 0x7b, 0x83)),
+        bg: Some((0xfd, 0xf6, 0xe3)),
+    },
+    Theme {
+        name: "solarized-dark",
+        fg: Some((0x83, 0x94, 0x96)),
+        bg: Some((0x00, 0x2b, 0x36)),
+    },
+    Theme {
+        name: "gruvbox",
+        fg: Some((0xeb, 0xdb, 0xb2)),
+        bg: Some((0x28, 0x28, 0x28)),
+    },
+    Theme {
+        name: "high-contrast",
+        fg: Some((0xff, 0xff, 0xff)),
+        bg: Some((0x00, 0x00, 0x00)),
+    },
+];
+
+// index into THEMES
+pub fn find(name: &str) -> Option<usize> {
+    THEMES.iter().position(|t| t.name == name)
+}
+
+// light or dark, for the terminal's background
+pub fn auto(background: (u8, u8, u8)) -> usize {
+    let name = if palette::light(background) {
+        "light"
+    } else {
+        "dark"
+    };
+    find(name).unwrap_or(0)
+}
+
+// eg 282a36
+pub fn hex(s: &str) -> Option<(u8, u8, u8)> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    if s.len() != 6 {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+    Some((byte(0)?, byte(2)?, byte(4)?))
+}
@@ -3,31 +3,142 @@ use crossterm::{
         KeyCode::{self, *},
         MouseEvent, MouseEventKind,
     },
-    style::Attribute::*,
+    style::{Attribute, Attribute::*, Attributes, Color},
 };
-use std::cmp::{min, Ordering};
-use unicode_width::UnicodeWidthChar;
+use std::{
+    any::TypeId,
+    cmp::{max, min, Ordering},
+    iter,
+};
+use unicode_bidi::{BidiInfo, Level};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::{Bk, Direction, SearchArgs};
+use crate::{
+    cjk,
+    css::Align,
+    epub::{self, Chapter, ATTRS},
+    graphics::{self, Protocol, Sixel},
+    i18n::KEYS,
+    palette, term, Bk, Direction, SearchArgs, GAP,
+};
 
-pub trait View {
+pub trait View: 'static {
     fn render(&self, bk: &Bk) -> Vec<String>;
     fn on_key(&self, bk: &mut Bk, kc: KeyCode);
     fn on_mouse(&self, _: &mut Bk, _: MouseEvent) {}
+    // lines from the mouse wheel, negative is up
+    fn on_scroll(&self, _: &mut Bk, _: isize) {}
+    // text typed or pasted in one go
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        // a key may switch views, eg `/` followed by the query, which then
+        // takes the rest in one go
+        for (i, c) in text.char_indices() {
+            let view = bk.view;
+            view.on_key(bk, Char(c));
+            if !same(view, bk.view) {
+                let rest = &text[i + c.len_utf8()..];
+                if !rest.is_empty() {
+                    bk.view.on_paste(bk, rest);
+                }
+                return;
+            }
+        }
+    }
     fn on_resize(&self, _: &mut Bk) {}
+    // the text is on screen, to count as read
+    fn reading(&self) -> bool {
+        false
+    }
+    // a key held with ctrl, the same as without unless it means something
+    fn on_ctrl(&self, bk: &mut Bk, c: char) {
+        self.on_key(bk, Char(c));
+    }
+    // which view this is. they're zero sized, their addresses all alike
+    fn id(&self) -> TypeId {
+        TypeId::of::<Self>()
+    }
+}
+
+fn same(a: &dyn View, b: &dyn View) -> bool {
+    a.id() == b.id()
+}
+
+// a move n times, or until it gets nowhere, eg at the end of the book, so
+// a huge count doesn't hang
+fn repeat(bk: &mut Bk, n: usize, f: impl Fn(&mut Bk)) {
+    for _ in 0..n {
+        let (at, view) = ((bk.chapter, bk.line), bk.view);
+        f(bk);
+        if (bk.chapter, bk.line) == at || !same(view, bk.view) {
+            break;
+        }
+    }
+}
+
+// the marks set, each with its chapter and the text there
+fn marks(bk: &Bk, prompt: &str) -> Vec<String> {
+    let mut marks: Vec<_> = bk.mark.iter().collect();
+    marks.sort();
+    let mut lines: Vec<String> = marks
+        .into_iter()
+        .take(bk.rows.saturating_sub(2))
+        .map(|(&m, &(c, byte))| {
+            let chapter = &bk.chapters[c];
+            let text = chapter
+                .text
+                .get(byte..)
+                .and_then(|s| s.lines().next())
+                .unwrap_or_default();
+            let line = format!(
+                "{}  {}  {}{}{}",
+                m, chapter.title, Dim, text, NormalIntensity
+            );
+            crop(&line, 0, bk.width())
+        })
+        .collect();
+    lines.push(String::new());
+    lines.push(prompt.to_string());
+    lines
 }
 
-// TODO render something useful?
 struct Mark;
 impl View for Mark {
     fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
-        if let Char(c) = kc {
-            bk.mark(c)
+        match kc {
+            Backspace | Delete => {
+                bk.view = &Unmark;
+                return;
+            }
+            Char(c) => {
+                bk.mark(c);
+                bk.msg = format!("{}: {}", bk.text.mark_set, c);
+            }
+            _ => (),
+        }
+        bk.view = &Page
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        marks(bk, bk.text.mark_prompt)
+    }
+}
+
+// m then Delete. ' stays, searches go back to it
+struct Unmark;
+impl View for Unmark {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Char(c) if c != '\'' => {
+                bk.msg = match bk.mark.remove(&c) {
+                    Some(_) => format!("{}: {}", bk.text.mark_deleted, c),
+                    None => format!("{}: {}", bk.text.no_mark, c),
+                }
+            }
+            _ => (),
         }
         bk.view = &Page
     }
     fn render(&self, bk: &Bk) -> Vec<String> {
-        Page::render(&Page, bk)
+        marks(bk, bk.text.unmark_prompt)
     }
 }
 
@@ -35,74 +146,179 @@ struct Jump;
 impl View for Jump {
     fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
         if let Char(c) = kc {
-            if let Some(&pos) = bk.mark.get(&c) {
-                bk.jump(pos);
+            match bk.mark.get(&c) {
+                Some(&pos) => bk.jump(pos),
+                None => bk.msg = format!("{}: {}", bk.text.no_mark, c),
             }
         }
         bk.view = &Page;
     }
     fn render(&self, bk: &Bk) -> Vec<String> {
-        Page::render(&Page, bk)
+        marks(bk, bk.text.jump_prompt)
+    }
+}
+
+// digits before a key repeat it, eg 12j. None while they're typed, shown
+// on the last row
+fn count(bk: &mut Bk, kc: KeyCode) -> Option<usize> {
+    let count = std::mem::take(&mut bk.count);
+    if let Char(c @ '0'..='9') = kc {
+        let digit = c.to_digit(10).unwrap() as usize;
+        bk.count = min(count * 10 + digit, 9_999_999);
+        bk.msg = bk.count.to_string();
+        return None;
     }
+    Some(count)
+}
+
+// lines of a view that can be taller than the screen, scrolled with the wheel
+fn scroll(bk: &mut Bk, n: isize, len: usize) {
+    let max = len.saturating_sub(bk.rows) as isize;
+    bk.scroll = (bk.scroll as isize + n).clamp(0, max) as usize;
+}
+fn scrolled(bk: &Bk, lines: Vec<String>) -> Vec<String> {
+    lines.into_iter().skip(bk.scroll).collect()
 }
 
 struct Metadata;
 impl View for Metadata {
     fn on_key(&self, bk: &mut Bk, _: KeyCode) {
+        bk.scroll = 0;
         bk.view = &Page;
     }
+    fn on_scroll(&self, bk: &mut Bk, n: isize) {
+        scroll(bk, n, self.lines(bk).len());
+    }
     fn render(&self, bk: &Bk) -> Vec<String> {
-        let lines: Vec<usize> = bk.chapters.iter().map(|c| c.lines.len()).collect();
-        let current = lines[..bk.chapter].iter().sum::<usize>() + bk.line;
-        let total = lines.iter().sum::<usize>();
+        scrolled(bk, self.lines(bk))
+    }
+}
+impl Metadata {
+    fn lines(&self, bk: &Bk) -> Vec<String> {
+        // by bytes, as other chapters may be wrapped at an old size
+        let byte = bk.chapters[bk.chapter].lines[bk.line].0;
+        let bytes: Vec<usize> = bk.chapters.iter().map(|c| c.text.len()).collect();
+        let current = bytes[..bk.chapter].iter().sum::<usize>() + byte;
+        let total = bytes.iter().sum::<usize>();
         let progress = current as f32 / total as f32 * 100.0;
 
-        let pages = (lines[bk.chapter] as f32 / bk.rows as f32).ceil() as usize;
-        // if the last line is visible we're on the last page. first page is the short one
-        let page = pages - (lines[bk.chapter] - 1 - bk.line) / bk.rows;
+        let location = bk.location();
+
+        let lines = bk.chapters[bk.chapter].lines.len();
+        let (page, pages) = if bk.paged {
+            pages(bk)
+        } else {
+            let pages = (lines as f32 / bk.rows as f32).ceil() as usize;
+            // if the last line is visible we're on the last page. first page is the short one
+            (pages - (lines - 1 - bk.line) / bk.rows, pages)
+        };
 
         let mut vec = vec![
-            format!("chapter: {}/{}", page, pages),
-            format!("total: {:.0}%", progress),
-            String::new(),
+            format!("{}: {}/{}", bk.text.chapter, page, pages),
+            format!("{}: {:.0}%", bk.text.total, progress),
+            format!("{}: {}/{}", bk.text.location, location.0, location.1),
         ];
+        // pages carry over from earlier chapters
+        let print = bk.chapters[bk.chapter].page(byte).or_else(|| {
+            bk.chapters[..bk.chapter]
+                .iter()
+                .rev()
+                .find_map(|c| c.pages.last().map(|p| p.1.as_str()))
+        });
+        if let Some(page) = print {
+            vec.push(format!("{}: {}", bk.text.page, page));
+        }
+        let breadcrumb = bk.breadcrumb();
+        if !breadcrumb.is_empty() {
+            vec.push(format!("{}: {}", bk.text.section, breadcrumb.join(" ▸ ")));
+        }
+        vec.push(String::new());
         vec.extend_from_slice(&bk.meta);
         vec
     }
 }
 
+// the rating and review, the review being typed if editing
+fn review(bk: &Bk, editing: bool) -> Vec<String> {
+    let rating = bk.book.rating as usize;
+    let stars = "★".repeat(rating) + &"☆".repeat(5 - rating);
+    let review = match editing {
+        true => format!("{}: {}_", bk.text.review, bk.book.review),
+        false => format!(
+            "{}: {}  ({})",
+            bk.text.review, bk.book.review, bk.text.review_keys
+        ),
+    };
+    vec![
+        format!("{}: {}  ({})", bk.text.rating, stars, bk.text.rating_keys),
+        String::new(),
+        review,
+    ]
+}
+
+// also the end of the book, where reading keys may still be coming, so the
+// review is only typed into after Enter
+struct Review;
+impl View for Review {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Esc => bk.view = &Page,
+            Char('q') => bk.quit = true,
+            Enter => bk.view = &EditReview,
+            Left => bk.book.rating = bk.book.rating.saturating_sub(1),
+            Right => bk.book.rating = min(5, bk.book.rating + 1),
+            Char(c @ '0'..='5') => bk.book.rating = c as u8 - b'0',
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        review(bk, false)
+    }
+}
+
+struct EditReview;
+impl View for EditReview {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Esc | Enter => bk.view = &Review,
+            Backspace => {
+                bk.book.review.pop();
+            }
+            Char(c) => bk.book.review.push(c),
+            _ => (),
+        }
+    }
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        bk.book.review.push_str(text);
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        review(bk, true)
+    }
+}
+
 struct Help;
 impl View for Help {
     fn on_key(&self, bk: &mut Bk, _: KeyCode) {
+        bk.scroll = 0;
         bk.view = &Page;
     }
-    fn render(&self, _: &Bk) -> Vec<String> {
-        let text = r#"
-                   Esc q  Quit
-                      Fn  Help
-                     Tab  Table of Contents
-                       i  Progress and Metadata
-
-PageDown Right Space f l  Page Down
-         PageUp Left b h  Page Up
-                       d  Half Page Down
-                       u  Half Page Up
-                  Down j  Line Down
-                    Up k  Line Up
-                  Home g  Chapter Start
-                   End G  Chapter End
-                       [  Previous Chapter
-                       ]  Next Chapter
-
-                       /  Search Forward
-                       ?  Search Backward
-                       n  Repeat search forward
-                       N  Repeat search backward
-                      mx  Set mark x
-                      'x  Jump to mark x
-                   "#;
-
-        text.lines().map(String::from).collect()
+    fn on_scroll(&self, bk: &mut Bk, n: isize) {
+        scroll(bk, n, self.lines(bk).len());
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        scrolled(bk, self.lines(bk))
+    }
+}
+impl Help {
+    fn lines(&self, bk: &Bk) -> Vec<String> {
+        let help = KEYS.iter().zip(bk.text.help).map(|(keys, what)| {
+            if keys.is_empty() {
+                String::new()
+            } else {
+                format!("{:>24}  {}", keys, what)
+            }
+        });
+        iter::once(String::new()).chain(help).collect()
     }
 }
 
@@ -133,14 +349,22 @@ impl View for Toc {
         self.cursor(bk);
     }
     fn on_mouse(&self, bk: &mut Bk, e: MouseEvent) {
-        match e.kind {
-            MouseEventKind::Down(_) => self.click(bk, e.row as usize),
-            MouseEventKind::ScrollDown => self.next(bk, 3),
-            MouseEventKind::ScrollUp => self.prev(bk, 3),
-            _ => (),
+        if let MouseEventKind::Down(_) = e.kind {
+            self.click(bk, e.row as usize);
+        }
+    }
+    fn on_scroll(&self, bk: &mut Bk, n: isize) {
+        if n > 0 {
+            self.next(bk, n as usize);
+        } else {
+            self.prev(bk, -n as usize);
         }
     }
     fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        let Some(count) = count(bk, kc) else {
+            return;
+        };
+        let n = count.max(1);
         match kc {
             Esc | Tab | Left | Char('h' | 'q') => {
                 bk.jump_reset();
@@ -152,14 +376,24 @@ impl View for Toc {
                 bk.cursor = 0;
                 bk.view = &Page;
             }
-            Down | Char('j') => self.next(bk, 1),
-            Up | Char('k') => self.prev(bk, 1),
+            Down | Char('j') => self.next(bk, n),
+            Up | Char('k') => self.prev(bk, n),
+            // to the nth entry, as vim's 12G
+            Char('g' | 'G') if count > 0 => {
+                bk.chapter = min(count - 1, bk.chapters.len() - 1);
+                self.cursor(bk);
+            }
             Home | Char('g') => self.prev(bk, bk.chapters.len()),
             End | Char('G') => self.next(bk, bk.chapters.len()),
-            PageDown | Char('f') => self.next(bk, bk.rows),
-            PageUp | Char('b') => self.prev(bk, bk.rows),
-            Char('d') => self.next(bk, bk.rows / 2),
-            Char('u') => self.prev(bk, bk.rows / 2),
+            Char('/') => {
+                bk.filter.clear();
+                bk.cursor = 0;
+                bk.view = &TocFilter;
+            }
+            PageDown | Char('f') => self.next(bk, bk.rows * n),
+            PageUp | Char('b') => self.prev(bk, bk.rows * n),
+            Char('d') => self.next(bk, bk.rows / 2 * n),
+            Char('u') => self.prev(bk, bk.rows / 2 * n),
             _ => (),
         }
     }
@@ -167,15 +401,428 @@ impl View for Toc {
         let start = bk.chapter - bk.cursor;
         let end = min(bk.chapters.len(), start + bk.rows);
 
+        // with a query, how often it matches in each chapter
+        let hits = (!bk.query.is_empty()).then(|| bk.hits());
         let mut arr = bk.chapters[start..end]
             .iter()
-            .map(|c| c.title.clone())
+            .enumerate()
+            .map(|(i, c)| {
+                let mut after = String::new();
+                match hits.as_ref().map_or(0, |h| h[start + i].len()) {
+                    0 => (),
+                    n => after.push_str(&format!(" ({})", n)),
+                }
+                // how far it's been read
+                let seen = bk.book.seen.get(&(start + i)).map_or(0, |p| p.byte);
+                let len = c.text.trim_end().len();
+                if seen >= len && len > 0 {
+                    after.push_str(" ✓");
+                } else if seen > 0 {
+                    after.push_str(&format!(" {}%", seen * 100 / len));
+                }
+                if after.is_empty() {
+                    return c.title.clone();
+                }
+                let room = bk.width().saturating_sub(after.width());
+                format!(
+                    "{}{}{}{}",
+                    crop(&c.title, 0, room),
+                    Dim,
+                    after,
+                    NormalIntensity
+                )
+            })
             .collect::<Vec<String>>();
         arr[bk.cursor] = format!("{}{}{}", Reverse, arr[bk.cursor], NoReverse);
         arr
     }
 }
 
+// named places saved with the book, to jump to and delete
+struct Bookmarks;
+impl Bookmarks {
+    fn close(&self, bk: &mut Bk) {
+        bk.cursor = 0;
+        bk.view = &Page;
+    }
+}
+impl View for Bookmarks {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        let len = bk.book.bookmarks.len();
+        match kc {
+            Esc | Char('q' | 'B') => self.close(bk),
+            // named at the command line
+            Char('a') => {
+                bk.cursor = 0;
+                bk.command = String::from("bookmark ");
+                bk.view = &Command;
+            }
+            _ if len == 0 => (),
+            Enter => {
+                let place = &bk.book.bookmarks[bk.cursor].1;
+                let pos = (place.chapter, place.byte);
+                self.close(bk);
+                bk.jump(pos);
+            }
+            Delete | Char('d') => {
+                bk.book.bookmarks.remove(bk.cursor);
+                bk.cursor = bk.cursor.min(len.saturating_sub(2));
+            }
+            Down | Char('j') => bk.cursor = min(bk.cursor + 1, len - 1),
+            Up | Char('k') => bk.cursor = bk.cursor.saturating_sub(1),
+            Home | Char('g') => bk.cursor = 0,
+            End | Char('G') => bk.cursor = len - 1,
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let rows = bk.rows.saturating_sub(2);
+        let start = (bk.cursor + 1).saturating_sub(rows);
+        let mut lines: Vec<String> = bk
+            .book
+            .bookmarks
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(rows)
+            .map(|(i, (name, place))| {
+                let title = &bk.chapters[place.chapter].title;
+                let text = place.snippet.lines().next().unwrap_or_default();
+                let line = format!("{}  {}  {}{}{}", name, title, Dim, text, NormalIntensity);
+                let line = crop(&line, 0, bk.width());
+                if i == bk.cursor {
+                    format!("{}{}{}", Reverse, line, NoReverse)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(String::new());
+        lines.push(bk.text.bookmarks_keys.to_string());
+        lines
+    }
+}
+
+// quoted text with notes, to jump to and delete
+struct Annotations;
+impl Annotations {
+    fn close(&self, bk: &mut Bk) {
+        bk.cursor = 0;
+        bk.view = &Page;
+    }
+}
+impl View for Annotations {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        let len = bk.book.annotations.len();
+        match kc {
+            Esc | Char('q' | 'A') => self.close(bk),
+            // noted at the command line
+            Char('a') => {
+                bk.cursor = 0;
+                bk.command = String::from("annotate ");
+                bk.view = &Command;
+            }
+            _ if len == 0 => (),
+            Enter => {
+                let a = &bk.book.annotations[bk.cursor];
+                let pos = (a.chapter, a.start);
+                self.close(bk);
+                bk.jump(pos);
+            }
+            Delete | Char('d') => {
+                bk.book.annotations.remove(bk.cursor);
+                bk.cursor = bk.cursor.min(len.saturating_sub(2));
+            }
+            Down | Char('j') => bk.cursor = min(bk.cursor + 1, len - 1),
+            Up | Char('k') => bk.cursor = bk.cursor.saturating_sub(1),
+            Home | Char('g') => bk.cursor = 0,
+            End | Char('G') => bk.cursor = len - 1,
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let rows = bk.rows.saturating_sub(2);
+        let start = (bk.cursor + 1).saturating_sub(rows);
+        let mut lines: Vec<String> = bk
+            .book
+            .annotations
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(rows)
+            .map(|(i, a)| {
+                let title = &bk.chapters[a.chapter].title;
+                let quote: Vec<_> = a.quote.split_whitespace().collect();
+                let lost = if a.lost { "✗ " } else { "" };
+                let line = format!(
+                    "{}{}  {}  {}{}{}",
+                    lost,
+                    title,
+                    quote.join(" "),
+                    Dim,
+                    a.note,
+                    NormalIntensity
+                );
+                let line = crop(&line, 0, bk.width());
+                if i == bk.cursor {
+                    format!("{}{}{}", Reverse, line, NoReverse)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(String::new());
+        lines.push(bk.text.annotations_keys.to_string());
+        lines
+    }
+}
+
+// words looked up in the book, the selected one's definition below them
+struct Words;
+impl Words {
+    fn close(&self, bk: &mut Bk) {
+        bk.cursor = 0;
+        bk.view = &Page;
+    }
+}
+impl View for Words {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        let len = bk.book.lookups.len();
+        match kc {
+            Esc | Char('q' | 'W') => self.close(bk),
+            // looked up at the command line
+            Char('a') => {
+                bk.cursor = 0;
+                bk.command = String::from("define ");
+                bk.view = &Command;
+            }
+            _ if len == 0 => (),
+            Enter => {
+                let place = &bk.book.lookups[bk.cursor].place;
+                let pos = (place.chapter, place.byte);
+                self.close(bk);
+                bk.jump(pos);
+            }
+            Delete | Char('d') => {
+                bk.book.lookups.remove(bk.cursor);
+                bk.cursor = bk.cursor.min(len.saturating_sub(2));
+            }
+            Down | Char('j') => bk.cursor = min(bk.cursor + 1, len - 1),
+            Up | Char('k') => bk.cursor = bk.cursor.saturating_sub(1),
+            Home | Char('g') => bk.cursor = 0,
+            End | Char('G') => bk.cursor = len - 1,
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let mut definition = Vec::new();
+        if let Some(l) = bk.book.lookups.get(bk.cursor) {
+            // wrap ends lines at newlines
+            let text = l.definition.clone() + "\n";
+            for (a, b) in crate::wrap(&text, bk.width(), None) {
+                definition.push(text[a..b].trim_end().to_string());
+            }
+            // keep at least half the screen for the list
+            definition.truncate((bk.rows / 2).saturating_sub(1));
+            definition.insert(0, String::new());
+        }
+        let rows = bk.rows.saturating_sub(2 + definition.len());
+        let start = (bk.cursor + 1).saturating_sub(rows);
+        let mut lines: Vec<String> = bk
+            .book
+            .lookups
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(rows)
+            .map(|(i, l)| {
+                let title = &bk.chapters[l.place.chapter].title;
+                let line = format!(
+                    "{}  {}{}  {}{}",
+                    l.word, Dim, title, l.definition, NormalIntensity
+                );
+                let line = crop(&line, 0, bk.width());
+                if i == bk.cursor {
+                    format!("{}{}{}", Reverse, line, NoReverse)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.extend(definition);
+        lines.push(String::new());
+        lines.push(bk.text.words_keys.to_string());
+        lines
+    }
+}
+
+// the jump list, newest first, to go back to any place in it
+struct Jumps;
+impl Jumps {
+    fn open(&self, bk: &mut Bk) {
+        if bk.jumps.is_empty() {
+            bk.msg = bk.text.no_jumps.to_string();
+            return;
+        }
+        // on the place back would go to
+        bk.cursor = min(bk.jumps.len() - bk.jump_at, bk.jumps.len() - 1);
+        bk.view = &Jumps;
+    }
+    fn close(&self, bk: &mut Bk) {
+        bk.cursor = 0;
+        bk.view = &Page;
+    }
+}
+impl View for Jumps {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        let len = bk.jumps.len();
+        match kc {
+            Esc | Char('q' | 'H') => self.close(bk),
+            Enter => {
+                let i = len - 1 - bk.cursor;
+                self.close(bk);
+                bk.go_jump(i);
+            }
+            Down | Char('j') => bk.cursor = min(bk.cursor + 1, len - 1),
+            Up | Char('k') => bk.cursor = bk.cursor.saturating_sub(1),
+            Home | Char('g') => bk.cursor = 0,
+            End | Char('G') => bk.cursor = len - 1,
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let start = (bk.cursor + 1).saturating_sub(bk.rows);
+        (0..bk.jumps.len())
+            .rev()
+            .enumerate()
+            .skip(start)
+            .take(bk.rows)
+            .map(|(row, i)| {
+                let (c, byte) = bk.jumps[i];
+                let chapter = &bk.chapters[c];
+                // the text there, to know it by
+                let text = chapter
+                    .text
+                    .get(byte..)
+                    .and_then(|s| s.lines().next())
+                    .unwrap_or_default();
+                // a dot where back and forward are
+                let here = if i == bk.jump_at { '•' } else { ' ' };
+                let line = format!(
+                    "{} {}  {}{}{}",
+                    here, chapter.title, Dim, text, NormalIntensity
+                );
+                let line = crop(&line, 0, bk.width());
+                if row == bk.cursor {
+                    format!("{}{}{}", Reverse, line, NoReverse)
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+}
+
+// chapters whose titles have the letters typed in order, like fzf, the
+// closest together first
+struct TocFilter;
+impl TocFilter {
+    // (chapter, where the letters matched in its title)
+    fn matches(&self, bk: &Bk) -> Vec<(usize, Vec<usize>)> {
+        let mut found: Vec<_> = bk
+            .chapters
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| fuzzy(&bk.filter, &c.title).map(|m| (i, m)))
+            .collect();
+        // by the span the letters cover, then where they start
+        found.sort_by_key(|(i, m)| {
+            let span = match (m.first(), m.last()) {
+                (Some(a), Some(b)) => b - a,
+                _ => 0,
+            };
+            (span, m.first().copied().unwrap_or(0), *i)
+        });
+        found
+    }
+    fn close(&self, bk: &mut Bk) {
+        Toc.cursor(bk);
+        bk.view = &Toc;
+    }
+}
+impl View for TocFilter {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Esc => self.close(bk),
+            Enter => {
+                if let Some(&(c, _)) = self.matches(bk).get(bk.cursor) {
+                    bk.chapter = c;
+                    bk.line = 0;
+                    bk.cursor = 0;
+                    bk.view = &Page;
+                }
+            }
+            Backspace if bk.filter.is_empty() => self.close(bk),
+            Backspace => {
+                bk.filter.pop();
+                bk.cursor = 0;
+            }
+            Down => bk.cursor = min(bk.cursor + 1, self.matches(bk).len().saturating_sub(1)),
+            Up => bk.cursor = bk.cursor.saturating_sub(1),
+            Char(c) => self.on_paste(bk, &c.to_string()),
+            _ => (),
+        }
+    }
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        bk.filter.push_str(text);
+        bk.cursor = 0;
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let rows = bk.rows.saturating_sub(1);
+        let start = (bk.cursor + 1).saturating_sub(rows);
+        let mut lines: Vec<String> = self
+            .matches(bk)
+            .into_iter()
+            .enumerate()
+            .skip(start)
+            .take(rows)
+            .map(|(row, (c, m))| {
+                // the letters matched in bold
+                let line: String = bk.chapters[c]
+                    .title
+                    .char_indices()
+                    .map(|(i, ch)| match m.contains(&i) {
+                        true => format!("{}{}{}", Bold, ch, NormalIntensity),
+                        false => ch.to_string(),
+                    })
+                    .collect();
+                let line = crop(&line, 0, bk.width());
+                if row == bk.cursor {
+                    format!("{}{}{}", Reverse, line, NoReverse)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.resize(rows, String::new());
+        lines.push(format!("/{}", bk.filter));
+        lines
+    }
+}
+
+// the bytes in text where each letter of query is found in order, ignoring
+// case and spaces. None if they aren't all there
+fn fuzzy(query: &str, text: &str) -> Option<Vec<usize>> {
+    let mut found = Vec::new();
+    let mut chars = text.char_indices();
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q: Vec<char> = q.to_lowercase().collect();
+        let (i, _) = chars.find(|(_, c)| c.to_lowercase().eq(q.iter().copied()))?;
+        found.push(i);
+    }
+    Some(found)
+}
+
 pub struct Page;
 impl Page {
     fn next_chapter(&self, bk: &mut Bk) {
@@ -190,36 +837,111 @@ impl Page {
             bk.line = 0;
         }
     }
+    // rows of text on screen and the footnotes they refer to, wrapped below
+    // a rule
+    fn notes(&self, bk: &Bk) -> (usize, Vec<String>) {
+        let c = &bk.chapters[bk.chapter];
+        let lines = |rows: usize| {
+            let last = min(bk.line + rows * bk.columns(), c.lines.len());
+            let (start, end) = (c.lines[bk.line].0, c.lines[last - 1].1);
+            let mut lines = Vec::new();
+            for (_, url) in c.notes.iter().filter(|n| start <= n.0 && n.0 < end) {
+                let Some(&(chapter, byte)) = bk.links.get(url) else {
+                    continue;
+                };
+                // wrap ends lines at newlines
+                let note = bk.chapters[chapter].note(byte) + "\n";
+                for (a, b) in crate::wrap(&note, bk.width(), None) {
+                    lines.push(note[a..b].to_string());
+                }
+            }
+            // keep at least half the page for the text
+            lines.truncate((bk.rows / 2).saturating_sub(1));
+            if !lines.is_empty() {
+                lines.insert(0, "─".repeat(min(bk.width(), 20)));
+            }
+            lines
+        };
+        // fixed pages keep every row for the text
+        if !bk.footnotes || bk.paged {
+            return (bk.rows, Vec::new());
+        }
+        // fewer rows of text may refer to fewer notes. leave the gap
+        let rows = bk.rows - lines(bk.rows).len();
+        (rows, lines(rows))
+    }
+    // a screen forward, less the footnotes and the overlap
+    fn page(&self, bk: &Bk) -> usize {
+        if bk.paged {
+            return bk.screen();
+        }
+        bk.turn(self.notes(bk).0 * bk.columns())
+    }
     fn scroll_down(&self, bk: &mut Bk, n: usize) {
-        if bk.line + bk.rows < bk.chapters[bk.chapter].lines.len() {
-            bk.line += n;
-        } else {
+        let len = bk.chapters[bk.chapter].lines.len();
+        if bk.line + bk.screen() < len {
+            bk.line = min(bk.line + n, len - 1);
+        } else if bk.chapter < bk.chapters.len() - 1 {
             self.next_chapter(bk);
+        } else {
+            // end of the book
+            bk.view = &Review;
         }
+        bk.skip_repeated();
     }
     fn scroll_up(&self, bk: &mut Bk, n: usize) {
         if bk.line > 0 {
             bk.line = bk.line.saturating_sub(n);
         } else if bk.chapter > 0 {
             bk.chapter -= 1;
-            bk.line = bk.chapters[bk.chapter].lines.len().saturating_sub(bk.rows);
+            // the end needs the chapter's lines at this size
+            bk.wrap();
+            bk.line = bk.chapters[bk.chapter]
+                .lines
+                .len()
+                .saturating_sub(bk.screen());
         }
     }
-    fn click(&self, bk: &mut Bk, e: MouseEvent) {
+    // the index of the link under the mouse
+    fn link_at(&self, bk: &Bk, e: MouseEvent) -> Option<usize> {
         let c = &bk.chapters[bk.chapter];
-        let line = bk.line + e.row as usize;
+        let rows = self.notes(bk).0;
+        let span = bk.width() as u16 + GAP;
+        let k = (0..bk.columns()).find(|&k| {
+            let x = bk.column_x(k);
+            x <= e.column && e.column < x + span
+        })?;
+        let line = bk.line + k * rows + e.row as usize;
 
-        if e.column < bk.pad() || line >= c.lines.len() {
-            return;
+        if line >= c.lines.len() || e.row as usize >= rows {
+            return None;
         }
         let (start, end) = c.lines[line];
-        let line_col = (e.column - bk.pad()) as usize;
+        let text = &c.text[start..end];
+        let nowrap = c.is_nowrap(start);
+        let left = left(bk, c, start, end);
+        let col = (e.column - bk.column_x(k)) as usize;
+        if col < left {
+            return None;
+        }
+        let mut line_col = col - left;
+        if nowrap {
+            line_col += bk.hscroll;
+        }
 
+        let chars: Vec<_> = match visual(text, bk.rtl) {
+            Some(order) => order.into_iter().map(|(i, _)| i).collect(),
+            None => text.char_indices().map(|(i, _)| i).collect(),
+        };
         let mut cols = 0;
         let mut found = false;
         let mut byte = start;
-        for (i, c) in c.text[start..end].char_indices() {
-            cols += c.width().unwrap();
+        for i in chars {
+            let c = text[i..].chars().next().unwrap();
+            // soft hyphens aren't drawn
+            if c != '\u{ad}' {
+                cols += c.width().unwrap();
+            }
             if cols > line_col {
                 byte += i;
                 found = true;
@@ -228,47 +950,157 @@ impl Page {
         }
 
         if !found {
-            return;
+            return None;
         }
 
-        let r = c.links.binary_search_by(|&(start, end, _)| {
-            if start > byte {
-                Ordering::Greater
-            } else if end <= byte {
-                Ordering::Less
-            } else {
-                Ordering::Equal
-            }
-        });
-
-        if let Ok(i) = r {
-            let url = &c.links[i].2;
-            let &(c, byte) = bk.links.get(url).unwrap();
-            bk.mark('\'');
-            bk.jump_byte(c, byte);
+        c.links
+            .binary_search_by(|&(start, end, _)| {
+                if start > byte {
+                    Ordering::Greater
+                } else if end <= byte {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()
+    }
+    fn click(&self, bk: &mut Bk, e: MouseEvent) {
+        if let Some(i) = self.link_at(bk, e) {
+            let url = bk.chapters[bk.chapter].links[i].2.clone();
+            bk.follow(&url);
         }
     }
+    // links in the chapter that are on screen, by index
+    fn links(&self, bk: &Bk) -> Vec<usize> {
+        let c = &bk.chapters[bk.chapter];
+        let last = min(bk.line + self.notes(bk).0 * bk.columns(), c.lines.len());
+        let (start, end) = (c.lines[bk.line].0, c.lines[last - 1].1);
+        (0..c.links.len())
+            .filter(|&i| c.links[i].0 < end && c.links[i].1 > start)
+            .collect()
+    }
+    // the selected link, if it's on screen
+    fn selected(&self, bk: &Bk) -> Option<usize> {
+        let (chapter, i) = bk.link?;
+        (chapter == bk.chapter && self.links(bk).contains(&i)).then_some(i)
+    }
+    // select the next link on screen, or the one before
+    fn select(&self, bk: &mut Bk, forward: bool) {
+        let links = self.links(bk);
+        if links.is_empty() {
+            return;
+        }
+        let n = links.len();
+        let k = match self
+            .selected(bk)
+            .and_then(|i| links.iter().position(|&l| l == i))
+        {
+            Some(k) if forward => (k + 1) % n,
+            Some(k) => (k + n - 1) % n,
+            None if forward => 0,
+            None => n - 1,
+        };
+        bk.link = Some((bk.chapter, links[k]));
+        bk.msg = bk.target(&bk.chapters[bk.chapter].links[links[k]].2);
+    }
     fn start_search(&self, bk: &mut Bk, dir: Direction) {
-        bk.mark('\'');
+        bk.save_jump();
         bk.query.clear();
         bk.dir = dir;
         bk.view = &Search;
     }
 }
 impl View for Page {
+    // pasted text isn't commands, but may be a search, eg /query
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        let dir = match text.chars().next() {
+            Some('/') => Direction::Next,
+            Some('?') => Direction::Prev,
+            _ => return,
+        };
+        self.start_search(bk, dir);
+        Search.on_paste(bk, &text[1..]);
+    }
     fn on_mouse(&self, bk: &mut Bk, e: MouseEvent) {
         match e.kind {
+            MouseEventKind::Down(_) if bk.scrollbar && e.column + 1 == bk.cols => {
+                // the same part of the chapter
+                let len = bk.chapters[bk.chapter].lines.len();
+                let rows = self.notes(bk).0;
+                bk.save_jump();
+                bk.line = min(e.row as usize * len / rows, len - 1);
+            }
             MouseEventKind::Down(_) => self.click(bk, e),
-            MouseEventKind::ScrollDown => self.scroll_down(bk, 3),
-            MouseEventKind::ScrollUp => self.scroll_up(bk, 3),
+            MouseEventKind::Moved => {
+                let target = self
+                    .link_at(bk, e)
+                    .map(|i| bk.target(&bk.chapters[bk.chapter].links[i].2))
+                    .unwrap_or_default();
+                // leave other messages be
+                if bk.msg.is_empty() || bk.msg == bk.hover {
+                    bk.msg = target.clone();
+                }
+                bk.hover = target;
+            }
+            MouseEventKind::ScrollLeft if bk.rtl => self.next_chapter(bk),
+            MouseEventKind::ScrollRight if bk.rtl => self.prev_chapter(bk),
+            MouseEventKind::ScrollLeft => self.prev_chapter(bk),
+            MouseEventKind::ScrollRight => self.next_chapter(bk),
             _ => (),
         }
     }
+    fn on_scroll(&self, bk: &mut Bk, n: isize) {
+        if n > 0 {
+            self.scroll_down(bk, n as usize);
+        } else {
+            self.scroll_up(bk, -n as usize);
+        }
+    }
     fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        // pages turn the way the book reads
+        let kc = match kc {
+            Left if bk.rtl => Right,
+            Right if bk.rtl => Left,
+            Char('h') if bk.rtl => Char('l'),
+            Char('l') if bk.rtl => Char('h'),
+            kc => kc,
+        };
+        let Some(count) = count(bk, kc) else {
+            return;
+        };
+        let n = count.max(1);
+        // a link selected with shift-tab takes tab, enter and esc
+        let selected = self.selected(bk);
+        if selected.is_none() {
+            bk.link = None;
+        }
         match kc {
+            Tab if selected.is_some() => self.select(bk, true),
+            BackTab => self.select(bk, false),
+            Enter => match selected {
+                Some(i) => {
+                    bk.link = None;
+                    let url = bk.chapters[bk.chapter].links[i].2.clone();
+                    bk.follow(&url);
+                }
+                None => bk.forward(),
+            },
+            Esc if selected.is_some() => bk.link = None,
+            Char('y') => match selected {
+                Some(i) => {
+                    let url = bk.chapters[bk.chapter].links[i].2.clone();
+                    term::copy(&url);
+                    bk.msg = format!("{}: {}", bk.text.copied, epub::sanitize(&url));
+                    bk.copy.set(Some(url));
+                }
+                None => bk.msg = bk.text.no_link.to_string(),
+            },
+            Char('%') if count > 0 => bk.percent(min(count, 100)),
+            Char('G') if count > 0 => bk.go_location(count),
             Esc | Char('q') => bk.quit = true,
             Tab => {
-                bk.mark('\'');
+                bk.save_jump();
                 Toc.cursor(bk);
                 bk.view = &Toc;
             }
@@ -276,62 +1108,132 @@ impl View for Page {
             Char('m') => bk.view = &Mark,
             Char('\'') => bk.view = &Jump,
             Char('i') => bk.view = &Metadata,
-            Char('?') => self.start_search(bk, Direction::Prev),
-            Char('/') => self.start_search(bk, Direction::Next),
-            Char('N') => {
-                bk.search(SearchArgs {
-                    dir: Direction::Prev,
-                    skip: true,
+            Char('r') => bk.view = &Review,
+            Char('o') => bk.footnotes = !bk.footnotes,
+            Char('p') => bk.toggle_progress(),
+            Char('t') if !bk.eink => bk.next_theme(),
+            Char('P') => bk.plain = !bk.plain,
+            Char(':') => {
+                bk.command.clear();
+                bk.view = &Command;
+            }
+            Char('M') => {
+                bk.mouse = !bk.mouse;
+                bk.msg = String::from(if bk.mouse {
+                    bk.text.mouse_on
+                } else {
+                    bk.text.mouse_off
                 });
             }
-            Char('n') => {
-                bk.search(SearchArgs {
-                    dir: Direction::Next,
+            Backspace => bk.back(),
+            Char('H') => Jumps.open(bk),
+            Char('B') => bk.view = &Bookmarks,
+            Char('A') => bk.view = &Annotations,
+            Char('W') => bk.view = &Words,
+            Char('F') => {
+                bk.hint.clear();
+                bk.view = &Hints;
+            }
+            Char('?') => self.start_search(bk, Direction::Prev),
+            Char('/') => self.start_search(bk, Direction::Next),
+            Char('N' | 'n') => {
+                let dir = match kc {
+                    Char('N') => Direction::Prev,
+                    _ => Direction::Next,
+                };
+                let args = || SearchArgs {
+                    dir: dir.clone(),
                     skip: true,
-                });
+                };
+                bk.save_jump();
+                if (0..n).all(|_| bk.search(args())) {
+                    found(bk);
+                } else {
+                    bk.not_found();
+                }
             }
             End | Char('G') => {
-                bk.mark('\'');
-                bk.line = bk.chapters[bk.chapter].lines.len().saturating_sub(bk.rows);
+                bk.save_jump();
+                bk.line = bk.chapters[bk.chapter]
+                    .lines
+                    .len()
+                    .saturating_sub(bk.screen());
             }
             Home | Char('g') => {
-                bk.mark('\'');
+                bk.save_jump();
                 bk.line = 0;
             }
-            Char('d') => self.scroll_down(bk, bk.rows / 2),
-            Char('u') => self.scroll_up(bk, bk.rows / 2),
-            Up | Char('k') => self.scroll_up(bk, 3),
+            Char('d') => self.scroll_down(bk, bk.screen() / 2 * n),
+            Char('u') => self.scroll_up(bk, bk.screen() / 2 * n),
+            Up | Char('k') => self.scroll_up(bk, bk.step(bk.scroll_lines) * n),
+            // a page at a time, as pages stop at the end of a chapter
             Left | PageUp | Char('b' | 'h') => {
-                self.scroll_up(bk, bk.rows);
+                repeat(bk, n, |bk| self.scroll_up(bk, bk.turn(bk.screen())))
+            }
+            Down | Char('j') => self.scroll_down(bk, bk.step(bk.scroll_lines) * n),
+            Right | PageDown | Char('f' | 'l' | ' ') => {
+                repeat(bk, n, |bk| self.scroll_down(bk, self.page(bk)))
             }
-            Down | Char('j') => self.scroll_down(bk, 3),
-            Right | PageDown | Char('f' | 'l' | ' ') => self.scroll_down(bk, bk.rows),
-            Char('[') => self.prev_chapter(bk),
-            Char(']') => self.next_chapter(bk),
+            Char('[') => repeat(bk, n, |bk| self.prev_chapter(bk)),
+            Char(']') => repeat(bk, n, |bk| self.next_chapter(bk)),
+            Char('<') => bk.hscroll = bk.hscroll.saturating_sub(bk.width() / 2 * n),
+            Char('>') => bk.hscroll += bk.width() / 2 * n,
+            Char('-' | '+' | '=') => {
+                let step = min(5 * n, bk.cols as usize) as u16;
+                bk.set_width(match kc {
+                    Char('-') => bk.max_width.saturating_sub(step),
+                    _ => bk.max_width.saturating_add(step),
+                });
+            }
+            Char('c') => bk.left = !bk.left,
             _ => (),
         }
     }
+    fn reading(&self) -> bool {
+        true
+    }
+    fn on_ctrl(&self, bk: &mut Bk, c: char) {
+        match c {
+            // back from a link, as vim's jump back
+            'o' => bk.back(),
+            c => self.on_key(bk, Char(c)),
+        }
+    }
     fn on_resize(&self, bk: &mut Bk) {
         // lazy
         bk.line = min(bk.line, bk.chapters[bk.chapter].lines.len() - 1);
     }
     fn render(&self, bk: &Bk) -> Vec<String> {
         let c = &bk.chapters[bk.chapter];
-        let last_line = min(bk.line + bk.rows, c.lines.len());
+        let (rows, notes) = self.notes(bk);
+        let columns = bk.columns();
+        let last_line = min(bk.line + rows * columns, c.lines.len());
         let text_start = c.lines[bk.line].0;
         let text_end = c.lines[last_line - 1].1;
 
         let mut search = Vec::new();
-        if !bk.query.is_empty() {
-            let len = bk.query.len();
-            for (pos, _) in c.text[text_start..text_end].match_indices(&bk.query) {
-                search.push((text_start + pos, Reverse));
-                search.push((text_start + pos + len, NoReverse));
+        if let Some(re) = bk.pattern() {
+            for m in re.find_iter(&c.text[text_start..text_end]) {
+                // an empty match has nothing to show
+                if !m.is_empty() {
+                    search.push((text_start + m.start(), Reverse));
+                    search.push((text_start + m.end(), NoReverse));
+                }
             }
         }
-        let mut search = search.into_iter().peekable();
+        // where matches start, for the gutter
+        let hits: Vec<usize> = search.iter().step_by(2).map(|s| s.0).collect();
+        let search = search.into_iter().map(|(pos, attr)| (pos, Esc::Attr(attr)));
+        // the link selected with shift-tab
+        let selected = self.selected(bk).into_iter().flat_map(|i| {
+            let (start, end, _) = c.links[i];
+            [
+                (max(start, text_start), Esc::Attr(Reverse)),
+                (end, Esc::Attr(NoReverse)),
+            ]
+        });
 
-        let mut base = {
+        let base = {
             let start = match c.attrs.binary_search_by_key(&text_start, |&x| x.0) {
                 Ok(n) => n,
                 Err(n) => n - 1,
@@ -339,7 +1241,7 @@ impl View for Page {
 
             let map = c.attrs[start].2;
             let mut head = Vec::new();
-            for attr in [Bold, Italic, Underlined] {
+            for (attr, _) in ATTRS {
                 if map.has(attr) {
                     head.push((text_start, attr));
                 }
@@ -348,50 +1250,634 @@ impl View for Page {
                 .iter()
                 .take_while(|x| x.0 <= text_end)
                 .map(|x| (x.0, x.1));
-            head.into_iter().chain(tail).peekable()
+            head.into_iter()
+                .chain(tail)
+                .map(|(pos, attr)| (pos, Esc::Attr(attr)))
         };
 
-        let mut attrs = Vec::new();
-        loop {
-            match (search.peek(), base.peek()) {
-                (None, None) => break,
-                (Some(_), None) => {
-                    attrs.extend(search);
-                    break;
+        let colors = {
+            let start = c.colors.partition_point(|x| x.0 <= text_start);
+            let head = start
+                .checked_sub(1)
+                .and_then(|i| c.colors[i].1)
+                .map(|fg| (text_start, Esc::Fg(Some(fg))));
+            let tail = c.colors[start..]
+                .iter()
+                .take_while(|x| x.0 <= text_end)
+                .map(|&(pos, fg)| (pos, Esc::Fg(fg)));
+            head.into_iter().chain(tail)
+        };
+
+        // links out of the book, which the terminal can open
+        let links = c
+            .links
+            .iter()
+            .filter(|l| l.0 < text_end && l.1 > text_start)
+            .filter(|l| l.2.starts_with("http://") || l.2.starts_with("https://"))
+            .flat_map(|(start, end, url)| {
+                [
+                    (max(*start, text_start), Esc::Link(Some(url.as_str()))),
+                    (*end, Esc::Link(None)),
+                ]
+            });
+
+        // annotated text, underlined
+        let annotations = bk
+            .book
+            .annotations
+            .iter()
+            .filter(|a| a.chapter == bk.chapter && a.start < text_end && a.end > text_start)
+            .flat_map(|a| {
+                [
+                    (max(a.start, text_start), Esc::Attr(Underlined)),
+                    (a.end, Esc::Attr(NoUnderline)),
+                ]
+            });
+
+        // where they meet, search highlights come after the book's own
+        let mut attrs: Vec<_> = base
+            .chain(search)
+            // only the colors and links, when plain
+            .filter(|_| !bk.plain)
+            .chain(colors)
+            .chain(links)
+            .chain(annotations)
+            .chain(selected)
+            .collect();
+        attrs.sort_by_key(|a| a.0);
+        let mut attrs = attrs.into_iter().peekable();
+
+        let mut buf = Vec::with_capacity(last_line - bk.line);
+        // in effect, to carry through reordered lines
+        let mut state = Pen::default();
+        for (n, &(line_start, line_end)) in c.lines[bk.line..last_line].iter().enumerate() {
+            let mut pos = line_start;
+            // each line sets its own attributes, as columns are drawn across
+            let mut s = Pen::default().change(state, bk);
+            let text = &c.text[line_start..line_end];
+            if let Some(order) = visual(text, bk.rtl) {
+                let mut shown = state;
+                let chars: Vec<_> = text
+                    .char_indices()
+                    .map(|(i, c)| {
+                        while let Some((_, esc)) = attrs.next_if(|a| a.0 <= line_start + i) {
+                            state.apply(esc);
+                        }
+                        (i, c, state)
+                    })
+                    .collect();
+                while let Some((_, esc)) = attrs.next_if(|a| a.0 <= line_end) {
+                    state.apply(esc);
                 }
-                (None, Some(_)) => {
-                    attrs.extend(base);
-                    break;
+                for (i, rtl) in order {
+                    let n = chars.binary_search_by_key(&i, |x| x.0).unwrap();
+                    let (_, c, pen) = chars[n];
+                    s.push_str(&shown.change(pen, bk));
+                    shown = pen;
+                    s.push(if rtl { mirror(c) } else { c });
                 }
-                (Some(&s), Some(&b)) => {
-                    if s.0 < b.0 {
-                        attrs.push(s);
-                        search.next();
-                    } else {
-                        attrs.push(b);
-                        base.next();
+                s.push_str(&shown.change(state, bk));
+            } else {
+                while let Some((esc_pos, esc)) = attrs.next_if(|a| a.0 <= line_end) {
+                    s.push_str(&c.text[pos..esc_pos]);
+                    s.push_str(&esc.to_string(bk));
+                    state.apply(esc);
+                    pos = esc_pos;
+                }
+                s.push_str(&c.text[pos..line_end]);
+            }
+            // soft hyphens show only where a line breaks
+            if s.contains('\u{ad}') {
+                s = s.replace('\u{ad}', "");
+                if c.text[..line_end].ends_with('\u{ad}') {
+                    s.push('-');
+                }
+            }
+            let indent = c.indent(line_start, bk.width());
+            if c.is_nowrap(line_start) {
+                s = crop(&s, bk.hscroll, bk.width() - indent.width());
+            } else if bk.hyphenator.is_some()
+                && line_start < line_end
+                && c.text[..line_end].ends_with(char::is_alphabetic)
+                && c.text[line_end..].starts_with(char::is_alphabetic)
+                && !c.text[line_end..].starts_with(cjk)
+                && c.text[line_start..line_end].width() + indent.width() < bk.width()
+            {
+                // a word broken by hyphenation
+                s.push('-');
+            }
+            match pad(bk, c, line_start, line_end) {
+                0 => (),
+                n => s.insert_str(0, &format!("\x1b[{}C", n)),
+            }
+            if bk.rtl && !c.is_nowrap(line_start) {
+                // the gutter on the right
+                s.push_str(&gutter(&indent.chars().rev().collect::<String>()));
+            } else {
+                s.insert_str(0, &gutter(indent));
+            }
+            s.push_str(&state.change(Pen::default(), bk));
+            if bk.page_markers {
+                let next = c.lines.get(bk.line + n + 1).map_or(c.text.len(), |l| l.0);
+                let page = c
+                    .pages
+                    .iter()
+                    .rev()
+                    .find(|p| (line_start..next).contains(&p.0));
+                if let Some((_, page)) = page {
+                    s.push_str(&marker(bk, n / rows, page));
+                }
+            }
+            if bk.gutter {
+                let next = c.lines.get(bk.line + n + 1).map_or(c.text.len(), |l| l.0);
+                // the first mark set on the line, else a dot for a match
+                let mark = bk
+                    .mark
+                    .iter()
+                    .filter(|&(&m, &(chapter, byte))| {
+                        m != '\'' && chapter == bk.chapter && (line_start..next).contains(&byte)
+                    })
+                    .map(|(&m, _)| m)
+                    .min();
+                let hit = hits.iter().any(|h| (line_start..line_end).contains(h));
+                if let Some(sign) = mark.or(hit.then_some('•')) {
+                    s.push_str(&sign_column(bk, n / rows, sign));
+                }
+            }
+            buf.push(s);
+        }
+
+        for img in &c.images {
+            // the part in each column it reaches
+            for top in (0..columns).map(|k| bk.line + k * rows) {
+                let end = min(top + rows, last_line);
+                let first = max(img.line, top);
+                let mut last = min(img.line + img.rows, end);
+                if bk.graphics == Some(Protocol::Sixel) {
+                    // drawing on the last row can scroll the screen
+                    last = min(last, top + bk.rows - 1);
+                }
+                if first >= last {
+                    continue;
+                }
+                // crop to the visible rows
+                let s = match bk.graphics {
+                    Some(Protocol::Kitty) => {
+                        let rows = img.rows as u32;
+                        let y = (first - img.line) as u32 * img.height / rows;
+                        let h = (last - img.line) as u32 * img.height / rows - y;
+                        let mut s = String::new();
+                        if !img.sent.get() {
+                            let png = img.png();
+                            if png.is_empty() {
+                                continue;
+                            }
+                            s.push_str(&graphics::transmit(img.id, &png));
+                            img.sent.set(true);
+                        }
+                        let rows = (last - first) * (bk.leading + 1);
+                        s.push_str(&graphics::place(img.id, img.cols, rows, y, h));
+                        s
                     }
+                    Some(Protocol::Sixel) => {
+                        let mut cache = img.sixel.borrow_mut();
+                        let stale = !cache.as_ref().is_some_and(|s| {
+                            s.cols == img.cols && s.rows == img.rows && s.cell == bk.cell
+                        });
+                        if stale {
+                            *cache = Sixel::new(&img.png(), img.cols, img.rows, bk.cell);
+                        }
+                        match cache.as_ref() {
+                            Some(sixel) => {
+                                let ch = bk.cell.1;
+                                sixel.encode((first - img.line) * ch, (last - first) * ch)
+                            }
+                            None => continue,
+                        }
+                    }
+                    None => continue,
+                };
+                let room = bk.width().saturating_sub(img.cols);
+                let s = match c.align(img.pos) {
+                    Align::Center => format!("\x1b[{}C{}", room / 2, s),
+                    Align::Right => format!("\x1b[{}C{}", room, s),
+                    Align::Left => s,
+                };
+                // hide the placeholder under the image
+                let span = min(img.line + img.span, end);
+                for row in &mut buf[first - bk.line..span - bk.line] {
+                    row.clear();
                 }
+                buf[first - bk.line] = s;
             }
         }
-        let mut attrs = attrs.into_iter().peekable();
 
-        let mut buf = Vec::with_capacity(last_line - bk.line);
-        for &(mut pos, line_end) in &c.lines[bk.line..last_line] {
-            let mut s = String::new();
-            while let Some((attr_pos, attr)) = attrs.next_if(|a| a.0 <= line_end) {
-                s.push_str(&c.text[pos..attr_pos]);
-                s.push_str(&attr.to_string());
-                pos = attr_pos;
-            }
-            s.push_str(&c.text[pos..line_end]);
-            buf.push(s);
+        if columns > 1 {
+            // side by side. sixels move the cursor, so each column goes
+            // back to the row saved at its start
+            buf.resize(rows * columns, String::new());
+            let mut spread: Vec<_> = (0..rows).map(|_| String::from("\x1b7")).collect();
+            for (n, line) in buf.into_iter().enumerate() {
+                let x = bk.column_x(n / rows) + 1;
+                spread[n % rows].push_str(&format!("\x1b8\x1b[{}G{}", x, line));
+            }
+            buf = spread;
         }
 
+        if bk.scrollbar {
+            buf.resize(rows, String::new());
+            for (row, bar) in buf.iter_mut().zip(scrollbar(bk, rows)) {
+                row.push_str(&format!("\x1b[{}G{}", bk.cols, bar));
+            }
+        }
+        if !notes.is_empty() {
+            buf.resize(rows, String::new());
+            buf.extend(notes);
+        }
         buf
     }
 }
 
+// the last row, faint: progress gauges and the clock
+pub fn status(bk: &Bk) -> String {
+    let n = bk.columns();
+    let span = bk.width() * n + GAP as usize * (n - 1);
+    let mut right = Vec::new();
+    right.extend(counter(bk));
+    if bk.paged {
+        let (page, pages) = pages(bk);
+        right.push(format!("{}/{}", page, pages));
+    }
+    if bk.clock {
+        let (h, m) = term::local_time();
+        let read = bk.time_read().as_secs() / 60;
+        right.push(format!(
+            "{:02}:{:02}  +{}:{:02}",
+            h,
+            m,
+            read / 60,
+            read % 60
+        ));
+    }
+    let clock = right.join("  ");
+    let room = span.saturating_sub(clock.width());
+    let left = if bk.progress && !clock.is_empty() {
+        gauge(bk, room.saturating_sub(2))
+    } else if bk.progress {
+        gauge(bk, room)
+    } else {
+        String::new()
+    };
+    let gap = room.saturating_sub(left.width());
+    format!(
+        "{}{}{}{}{}",
+        Dim,
+        left,
+        " ".repeat(gap),
+        clock,
+        NormalIntensity
+    )
+}
+
+// the match on screen among those in the chapter, and in the whole book, eg
+// match 12/87 in chapter, 240 in book
+fn counter(bk: &Bk) -> Option<String> {
+    if bk.query.is_empty() {
+        return None;
+    }
+    let hits = bk.hits();
+    let chapter = &hits[bk.chapter];
+    let book: usize = hits.iter().map(Vec::len).sum();
+    let top = bk.chapters[bk.chapter].lines[bk.line].0;
+    let n = min(chapter.partition_point(|&h| h < top) + 1, chapter.len());
+    let t = bk.text;
+    Some(format!(
+        "{} {}/{} {}, {} {}",
+        t.matched,
+        n,
+        chapter.len(),
+        t.in_chapter,
+        book,
+        t.in_book
+    ))
+}
+
+// after a search, the count on the last row if there's no status row to show it
+fn found(bk: &mut Bk) {
+    if !(bk.progress || bk.clock || bk.paged) {
+        bk.msg = counter(bk).unwrap_or_default();
+    }
+}
+
+// faint track with the screen's place in the chapter, a row each
+fn scrollbar(bk: &Bk, rows: usize) -> Vec<String> {
+    let len = bk.chapters[bk.chapter].lines.len();
+    let start = bk.line * rows / len;
+    let end = ((bk.line + bk.screen()) * rows)
+        .div_ceil(len)
+        .clamp(start + 1, rows);
+    (0..rows)
+        .map(|row| {
+            let c = if (start..end).contains(&row) {
+                '┃'
+            } else {
+                '│'
+            };
+            format!("{}{}{}", Dim, c, NormalIntensity)
+        })
+        .collect()
+}
+
+// (page, pages) of the chapter in fixed pages
+fn pages(bk: &Bk) -> (usize, usize) {
+    let screen = bk.screen();
+    let len = bk.chapters[bk.chapter].lines.len();
+    (bk.line / screen + 1, len.div_ceil(screen))
+}
+
+// bars for the place in the chapter and in the book, side by side
+fn gauge(bk: &Bk, width: usize) -> String {
+    let c = &bk.chapters[bk.chapter];
+    let len = c.lines.len();
+    let end = min(bk.line + bk.screen(), len);
+    let chapter = end as f32 / len as f32;
+    // by bytes, as other chapters may be wrapped at an old size
+    let bytes: Vec<usize> = bk.chapters.iter().map(|c| c.text.len()).collect();
+    let current = bytes[..bk.chapter].iter().sum::<usize>() + c.lines[end - 1].1;
+    let book = current as f32 / bytes.iter().sum::<usize>() as f32;
+    let half = width.saturating_sub(2) / 2;
+    format!("{}  {}", bar(chapter, half), bar(book, half))
+}
+
+// a bar filled in eighths of a column
+fn bar(frac: f32, width: usize) -> String {
+    let eighths = (frac.clamp(0.0, 1.0) * (width * 8) as f32).round() as usize;
+    let (full, part) = (eighths / 8, eighths % 8);
+    let mut s = "█".repeat(full);
+    if part > 0 && full < width {
+        s.push(['▏', '▎', '▍', '▌', '▋', '▊', '▉'][part - 1]);
+    }
+    let filled = s.chars().count();
+    s + &"░".repeat(width - filled)
+}
+
+// columns before a line's text, with any gutter
+fn left(bk: &Bk, c: &Chapter, start: usize, end: usize) -> usize {
+    if bk.rtl && !c.is_nowrap(start) {
+        // the gutter is on the right
+        pad(bk, c, start, end)
+    } else {
+        c.indent(start, bk.width()).width() + pad(bk, c, start, end)
+    }
+}
+
+// columns before a line to align it, after any gutter
+fn pad(bk: &Bk, c: &Chapter, start: usize, end: usize) -> usize {
+    if c.is_nowrap(start) {
+        return 0;
+    }
+    let width = c.text[start..end].replace('\u{ad}', "").width();
+    let room = bk
+        .width()
+        .saturating_sub(c.indent(start, bk.width()).width() + width);
+    match c.align(start) {
+        Align::Center => room / 2,
+        Align::Right => room,
+        Align::Left if bk.rtl => room,
+        Align::Left => 0,
+    }
+}
+
+// byte offsets of the chars in display order and whether they read right to
+// left, or None if the line reads left to right
+fn visual(text: &str, rtl: bool) -> Option<Vec<(usize, bool)>> {
+    let info = BidiInfo::new(text, rtl.then(Level::rtl));
+    if !info.has_rtl() {
+        return None;
+    }
+    let mut order = Vec::new();
+    for para in &info.paragraphs {
+        let (levels, runs) = info.visual_runs(para, para.range.clone());
+        for run in runs {
+            let rtl = levels[run.start].is_rtl();
+            let chars = text[run.clone()]
+                .char_indices()
+                .map(|(i, _)| (run.start + i, rtl));
+            if rtl {
+                order.extend(chars.rev());
+            } else {
+                order.extend(chars);
+            }
+        }
+    }
+    Some(order)
+}
+
+// brackets face the other way in right to left text
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        _ => c,
+    }
+}
+
+// an attribute, text color or web link taking effect
+#[derive(Clone, Copy)]
+enum Esc<'a> {
+    Attr(Attribute),
+    // None for the reader's color
+    Fg(Option<Color>),
+    // None outside a link
+    Link(Option<&'a str>),
+}
+
+impl Esc<'_> {
+    fn to_string(self, bk: &Bk) -> String {
+        match self {
+            Esc::Attr(attr) => attr.to_string(),
+            // osc 8, for terminals to open with a click
+            Esc::Link(url) => format!("\x1b]8;;{}\x1b\\", encode(url.unwrap_or_default())),
+            Esc::Fg(fg) => {
+                // the book's color, unless it's lost on the background
+                let fg = fg
+                    .filter(|&c| bk.background.is_none_or(|bg| palette::readable(c, bg)))
+                    .or(bk.colors.foreground)
+                    .unwrap_or(Color::Reset);
+                bk.palette.fg(fg)
+            }
+        }
+    }
+}
+
+// attributes, text color and web link in effect
+#[derive(Clone, Copy, Default)]
+struct Pen<'a> {
+    attrs: Attributes,
+    fg: Option<Color>,
+    link: Option<&'a str>,
+}
+
+impl<'a> Pen<'a> {
+    fn apply(&mut self, esc: Esc<'a>) {
+        let attr = match esc {
+            Esc::Fg(fg) => return self.fg = fg,
+            Esc::Link(url) => return self.link = url,
+            Esc::Attr(attr) => attr,
+        };
+        match attr {
+            Reset => self.attrs = Attributes::default(),
+            NormalIntensity => {
+                self.attrs.unset(Bold);
+                self.attrs.unset(Dim);
+            }
+            NoItalic => self.attrs.unset(Italic),
+            NoUnderline => self.attrs.unset(Underlined),
+            NotCrossedOut => self.attrs.unset(CrossedOut),
+            NoReverse => self.attrs.unset(Reverse),
+            attr => self.attrs.set(attr),
+        }
+    }
+    // the escapes to go from this to another
+    fn change(self, to: Pen<'a>, bk: &Bk) -> String {
+        let mut s = String::new();
+        for (on, off) in ATTRS.into_iter().chain([(Reverse, NoReverse)]) {
+            if self.attrs.has(on) == to.attrs.has(on) {
+                continue;
+            }
+            if to.attrs.has(on) {
+                s.push_str(&on.to_string());
+                continue;
+            }
+            s.push_str(&off.to_string());
+            for other in [Bold, Dim] {
+                if off == NormalIntensity && to.attrs.has(other) {
+                    s.push_str(&other.to_string());
+                }
+            }
+        }
+        if self.fg != to.fg {
+            s.push_str(&Esc::Fg(to.fg).to_string(bk));
+        }
+        if self.link != to.link {
+            s.push_str(&Esc::Link(to.link).to_string(bk));
+        }
+        s
+    }
+}
+
+// a faint page number beside column k, after the line or before it right to
+// left. nothing if the margin or the gap is too narrow
+fn marker(bk: &Bk, k: usize, page: &str) -> String {
+    let room = if k + 1 < bk.columns() {
+        GAP as usize
+    } else {
+        bk.pad() as usize
+    };
+    let width = page.width();
+    if room < width + 1 {
+        return String::new();
+    }
+    let x = bk.column_x(k) as usize;
+    let col = if bk.rtl {
+        x - width - 1
+    } else {
+        x + bk.width() + 1
+    };
+    let faint = Pen {
+        attrs: Attributes::from(Dim),
+        ..Pen::default()
+    };
+    let pen = Pen::default();
+    format!(
+        "\x1b[{}G{}{}{}",
+        col + 1,
+        pen.change(faint, bk),
+        page,
+        faint.change(pen, bk)
+    )
+}
+
+// a faint sign before column k, or after it right to left, a space from the
+// text
+fn sign_column(bk: &Bk, k: usize, sign: char) -> String {
+    let room = if k > 0 { GAP } else { bk.pad() };
+    if room < 2 {
+        return String::new();
+    }
+    let x = bk.column_x(k) as usize;
+    let col = if bk.rtl { x + bk.width() + 1 } else { x - 2 };
+    format!("\x1b[{}G{}{}{}", col + 1, Dim, sign, NormalIntensity)
+}
+
+// move over spaces instead of printing them, which could be underlined
+fn gutter(prefix: &str) -> String {
+    let mut s = String::new();
+    let mut spaces = 0;
+    for c in prefix.chars().chain(iter::once('\0')) {
+        if c == ' ' {
+            spaces += 1;
+            continue;
+        }
+        if spaces > 0 {
+            s.push_str(&format!("\x1b[{}C", spaces));
+            spaces = 0;
+        }
+        if c != '\0' {
+            s.push(c);
+        }
+    }
+    s
+}
+
+// a url as printable ascii, so the book can't end the osc early
+fn encode(url: &str) -> String {
+    let mut s = String::new();
+    for b in url.bytes() {
+        match b {
+            b'!'..=b'~' => s.push(b as char),
+            b => s.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    s
+}
+
+// the columns from..from + width of a line, keeping any escape codes
+pub fn crop(line: &str, from: usize, width: usize) -> String {
+    let mut s = String::new();
+    let mut col = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            s.push(c);
+            // up to the final letter, or the \x1b\\ ending an osc like a link
+            let osc = chars.as_str().starts_with(']');
+            for c in chars.by_ref() {
+                let st = s.ends_with('\x1b') && c == '\\';
+                s.push(c);
+                if (!osc && c.is_ascii_alphabetic()) || (osc && st) {
+                    break;
+                }
+            }
+            continue;
+        }
+        let w = c.width().unwrap_or(0);
+        if col >= from && col + w <= from + width {
+            s.push(c);
+        }
+        col += w;
+    }
+    s
+}
+
 pub struct Search;
 impl View for Search {
     fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
@@ -402,6 +1888,17 @@ impl View for Search {
                 bk.view = &Page;
             }
             Enter => {
+                let args = SearchArgs {
+                    dir: bk.dir.clone(),
+                    skip: false,
+                };
+                if !bk.query.is_empty() {
+                    if bk.search(args) {
+                        found(bk);
+                    } else {
+                        bk.not_found();
+                    }
+                }
                 bk.view = &Page;
             }
             Backspace => {
@@ -412,33 +1909,304 @@ impl View for Search {
                     skip: false,
                 });
             }
-            Char(c) => {
-                bk.query.push(c);
-                let args = SearchArgs {
-                    dir: bk.dir.clone(),
-                    skip: false,
-                };
-                if !bk.search(args) {
-                    bk.jump_reset();
-                }
+            Char(c) => self.on_paste(bk, &c.to_string()),
+            Tab => {
+                bk.regex = !bk.regex;
+                self.on_paste(bk, "");
             }
             _ => (),
         }
     }
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        bk.query.push_str(text);
+        // from where the search started, as a longer regex can match sooner
+        bk.jump_reset();
+        bk.search(SearchArgs {
+            dir: bk.dir.clone(),
+            skip: false,
+        });
+    }
     fn render(&self, bk: &Bk) -> Vec<String> {
-        let mut buf = Page::render(&Page, bk);
-        if buf.len() == bk.rows {
-            buf.pop();
-        } else {
-            for _ in buf.len()..bk.rows - 1 {
-                buf.push(String::new());
-            }
-        }
         let prefix = match bk.dir {
             Direction::Next => '/',
             Direction::Prev => '?',
         };
-        buf.push(format!("{}{}", prefix, bk.query));
+        let mode = match bk.regex {
+            true => format!("{} ", bk.text.regex),
+            false => String::new(),
+        };
+        prompt(bk, format!("{}{}{}", mode, prefix, bk.query))
+    }
+}
+
+// the page with a line being typed on the last row
+fn prompt(bk: &Bk, line: String) -> Vec<String> {
+    let mut buf = Page::render(&Page, bk);
+    if buf.len() == bk.rows {
+        buf.pop();
+    } else {
+        for _ in buf.len()..bk.rows - 1 {
+            buf.push(String::new());
+        }
+    }
+    buf.push(line);
+    buf
+}
+
+// a label on each link on screen, typed to follow it
+struct Hints;
+impl Hints {
+    // (label, row, column) of each link and where it goes
+    fn labels(&self, bk: &Bk) -> Vec<(String, usize, u16, String)> {
+        let c = &bk.chapters[bk.chapter];
+        let rows = Page.notes(bk).0;
+        let last = min(bk.line + rows * bk.columns(), c.lines.len());
+        let mut spots = Vec::new();
+        for (n, &(start, end)) in c.lines[bk.line..last].iter().enumerate() {
+            let links = c.links.iter().filter(|l| {
+                // a link broken across lines is labeled once, on the page
+                let first = l.0 >= start || (n == 0 && l.1 > start);
+                first && l.0 < end
+            });
+            for (byte, _, url) in links {
+                let byte = max(*byte, start);
+                let text = &c.text[start..end];
+                let order: Vec<usize> = match visual(text, bk.rtl) {
+                    Some(order) => order.into_iter().map(|(i, _)| i).collect(),
+                    None => text.char_indices().map(|(i, _)| i).collect(),
+                };
+                // the columns drawn before the link, soft hyphens aren't
+                let col: usize = order
+                    .iter()
+                    .take_while(|&&i| start + i != byte)
+                    .map(|&i| text[i..].chars().next().unwrap())
+                    .filter(|&c| c != '\u{ad}')
+                    .map(|c| c.width().unwrap_or(0))
+                    .sum();
+                let col = if c.is_nowrap(start) {
+                    match col.checked_sub(bk.hscroll) {
+                        Some(col) if col < bk.width() => col,
+                        _ => continue,
+                    }
+                } else {
+                    col
+                };
+                let x = bk.column_x(n / rows) as usize + left(bk, c, start, end) + col;
+                spots.push((n % rows, x as u16, url.clone()));
+            }
+        }
+        // the same length for all, so none is the start of another
+        const LETTERS: &str = "asdfghjkl";
+        let mut len = 1;
+        while LETTERS.len().pow(len) < spots.len() {
+            len += 1;
+        }
+        spots
+            .into_iter()
+            .enumerate()
+            .map(|(i, (row, x, url))| {
+                let label = (0..len)
+                    .rev()
+                    .map(|d| LETTERS.as_bytes()[i / LETTERS.len().pow(d) % LETTERS.len()] as char)
+                    .collect();
+                (label, row, x, url)
+            })
+            .collect()
+    }
+}
+impl View for Hints {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Char(c) => bk.hint.push(c),
+            Backspace if !bk.hint.is_empty() => {
+                bk.hint.pop();
+                return;
+            }
+            _ => {
+                bk.view = &Page;
+                return;
+            }
+        }
+        let labels = self.labels(bk);
+        let mut left = labels.iter().filter(|l| l.0.starts_with(&bk.hint));
+        match (left.next(), left.next()) {
+            (Some(l), None) if l.0 == bk.hint => {
+                let url = l.3.clone();
+                bk.view = &Page;
+                bk.follow(&url);
+            }
+            (None, _) => bk.view = &Page,
+            _ => (),
+        }
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        let mut buf = Page::render(&Page, bk);
+        for (label, row, x, _) in self.labels(bk) {
+            // what's typed so far is faint
+            let Some(rest) = label.strip_prefix(&bk.hint) else {
+                continue;
+            };
+            if let Some(line) = buf.get_mut(row) {
+                line.push_str(&format!(
+                    "\x1b[{}G{}{}{}{}{}{}",
+                    x + 1,
+                    Reverse,
+                    Dim,
+                    bk.hint,
+                    NormalIntensity,
+                    rest,
+                    NoReverse
+                ));
+            }
+        }
         buf
     }
 }
+
+// names for Tab to complete
+const COMMANDS: [&str; 17] = [
+    "annotate",
+    "annotations",
+    "bookmark",
+    "bookmarks",
+    "chapter",
+    "define",
+    "help",
+    "info",
+    "jumps",
+    "location",
+    "mark",
+    "q",
+    "reanchor",
+    "set",
+    "theme",
+    "toc",
+    "words",
+];
+
+// ex style commands after :, for what has no key of its own
+struct Command;
+impl View for Command {
+    fn on_key(&self, bk: &mut Bk, kc: KeyCode) {
+        match kc {
+            Esc => bk.view = &Page,
+            Enter => {
+                bk.view = &Page;
+                let line = std::mem::take(&mut bk.command);
+                if run(bk, line.trim()).is_none() {
+                    bk.msg = format!("{}: {}", bk.text.no_command, line);
+                }
+            }
+            // past the start leaves
+            Backspace if bk.command.is_empty() => bk.view = &Page,
+            Backspace => {
+                bk.command.pop();
+            }
+            Tab if !bk.command.contains(' ') => {
+                if let Some(name) = COMMANDS.iter().find(|c| c.starts_with(&bk.command)) {
+                    bk.command = format!("{} ", name);
+                }
+            }
+            Char(c) => bk.command.push(c),
+            _ => (),
+        }
+    }
+    fn on_paste(&self, bk: &mut Bk, text: &str) {
+        bk.command.push_str(text);
+    }
+    fn render(&self, bk: &Bk) -> Vec<String> {
+        prompt(bk, format!(":{}", bk.command))
+    }
+}
+
+// a command line, eg theme sepia. None if it isn't one
+fn run(bk: &mut Bk, line: &str) -> Option<()> {
+    let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+    let arg = arg.trim();
+    let number = || arg.parse::<usize>().ok().filter(|&n| n > 0);
+    // as 50%
+    if let Ok(n) = line.trim_end_matches('%').parse::<usize>() {
+        bk.percent(min(n, 100));
+        return Some(());
+    }
+    match name {
+        "q" | "quit" => bk.quit = true,
+        "toc" => {
+            bk.save_jump();
+            Toc.cursor(bk);
+            bk.view = &Toc;
+        }
+        "help" => bk.view = &Help,
+        "jumps" => Jumps.open(bk),
+        "bookmark" if !arg.is_empty() => bk.bookmark(arg),
+        "bookmarks" => bk.view = &Bookmarks,
+        "annotate" => bk.annotate(arg),
+        "annotations" => bk.view = &Annotations,
+        // on the word just looked up
+        "define" => {
+            if bk.define(arg) {
+                bk.cursor = bk.book.lookups.len() - 1;
+                bk.view = &Words;
+            }
+        }
+        "words" => bk.view = &Words,
+        // the lost ones to look at
+        "reanchor" => {
+            bk.reanchor();
+            if bk.book.annotations.iter().any(|a| a.lost) {
+                bk.view = &Annotations;
+            }
+        }
+        "info" => bk.view = &Metadata,
+        "mark" => {
+            let mut chars = arg.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+            bk.mark(c);
+            bk.msg = format!("{}: {}", bk.text.mark_set, c);
+        }
+        "chapter" => {
+            let n = min(number()?, bk.chapters.len());
+            bk.jump((n - 1, 0));
+        }
+        "location" => bk.go_location(number()?),
+        "theme" => bk.set_theme(crate::theme::find(arg)?),
+        "set" => match arg.split_once('=') {
+            Some(("width", n)) => bk.set_width(n.trim().parse().ok()?),
+            Some(_) => return None,
+            // booleans, eg set plain, set noplain
+            None => {
+                let (on, name) = match arg.strip_prefix("no") {
+                    Some(name) => (false, name),
+                    None => (true, arg),
+                };
+                match name {
+                    "plain" => bk.plain = on,
+                    "footnotes" => bk.footnotes = on,
+                    "gutter" => bk.gutter = on,
+                    "left" => bk.left = on,
+                    "mouse" => bk.mouse = on,
+                    "progress" if bk.progress != on => bk.toggle_progress(),
+                    "progress" => (),
+                    _ => return None,
+                }
+            }
+        },
+        _ => return None,
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_differ() {
+        let page: &dyn View = &Page;
+        assert!(same(page, page));
+        assert!(!same(page, &Search));
+        assert!(!same(&Toc, &TocFilter));
+    }
+}